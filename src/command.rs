@@ -0,0 +1,78 @@
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json;
+use message::Entry;
+use errors::*;
+
+#[derive(Serialize)]
+struct Input<'a> {
+    feed: &'a str,
+    entry: &'a Entry,
+}
+
+/// Pipe `entry` through an external command.
+///
+/// The command receives `{"feed": ..., "entry": {...}}` as JSON on its
+/// standard input and prints the modified entry as JSON on its standard
+/// output, or prints nothing to drop the entry. It is killed after
+/// `timeout`.
+pub fn apply(command: &str, timeout: Duration, feed_name: &str, entry: Entry) -> Result<Option<Entry>> {
+    let input = serde_json::to_vec(&Input {
+                                        feed: feed_name,
+                                        entry: &entry,
+                                    })
+            .chain_err(|| "could not serialize entry")?;
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .chain_err(|| format!("could not run {}", command))?;
+
+    // feed and drain the pipes from threads so a chatty command can not block us
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = thread::spawn(move || stdin.write_all(&input));
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+                                   let mut output = Vec::new();
+                                   stdout.read_to_end(&mut output).map(|_| output)
+                               });
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() > timeout {
+            child.kill()?;
+            child.wait()?;
+            bail!("{} timed out after {}s", command, timeout.as_secs());
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+
+    // the command may exit without reading its input
+    let _ = writer.join();
+    let output = match reader.join() {
+        Ok(output) => output?,
+        Err(_) => bail!("could not read the output of {}", command),
+    };
+
+    if !status.success() {
+        bail!("{} failed with {}", command, status);
+    }
+
+    if output.iter().all(|b| (*b as char).is_whitespace()) {
+        return Ok(None);
+    }
+
+    let mut processed: Entry = serde_json::from_slice(&output)
+        .chain_err(|| format!("invalid entry returned by {}", command))?;
+    // the id is what we remember the entry by
+    processed.id = entry.id;
+    Ok(Some(processed))
+}
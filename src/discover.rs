@@ -0,0 +1,97 @@
+use std::default::Default;
+use html5ever::parse_document;
+use html5ever::rcdom::{Element, Handle, RcDom};
+use html5ever::tendril::TendrilSink;
+use reqwest::Url;
+use http;
+use message::Messages;
+use errors::*;
+
+const FEED_TYPES: &'static [&'static str] = &["application/rss+xml",
+                                               "application/atom+xml",
+                                               "application/feed+json"];
+
+/// A feed advertised by a web page with `<link rel="alternate">`.
+pub struct Link {
+    pub url: String,
+    pub title: Option<String>,
+    pub mime: String,
+}
+
+fn find_links(handle: &Handle, base: &Url, links: &mut Vec<Link>) {
+    let node = handle.borrow();
+    if let Element(ref name, _, ref attrs) = node.node {
+        if &*name.local == "link" {
+            let mut rel = String::new();
+            let mut mime = String::new();
+            let mut href = None;
+            let mut title = None;
+            for attr in attrs {
+                match &*attr.name.local {
+                    "rel" => rel = attr.value.to_lowercase(),
+                    "type" => mime = attr.value.trim().to_lowercase(),
+                    "href" => href = Some(attr.value.to_string()),
+                    "title" => title = Some(attr.value.to_string()),
+                    _ => {}
+                }
+            }
+            if rel.split_whitespace().any(|rel| rel == "alternate") &&
+               FEED_TYPES.contains(&mime.as_str()) {
+                if let Some(Ok(url)) = href.map(|href| base.join(&href)) {
+                    links.push(Link {
+                                   url: url.to_string(),
+                                   title: title,
+                                   mime: mime,
+                               });
+                }
+            }
+        }
+    }
+    for child in node.children.iter() {
+        find_links(child, base, links);
+    }
+}
+
+/// Feeds advertised by an HTML page located at `base`.
+pub fn links(html: &str, base: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+    if let Ok(base) = Url::parse(base) {
+        let dom = parse_document(RcDom::default(), Default::default()).one(html);
+        find_links(&dom.document, &base, &mut links);
+    }
+    links
+}
+
+/// Location and title of the feed at `url`, or of the one advertised by
+/// the page at `url`.
+pub fn find(url: &str) -> Result<(String, String)> {
+    let data = http::get(url)?;
+    if let Ok(messages) = Messages::new(&data) {
+        return Ok((url.to_string(), messages.feed_name));
+    }
+
+    let (links, json): (Vec<Link>, Vec<Link>) = links(&data, url)
+        .into_iter()
+        .partition(|link| link.mime != "application/feed+json");
+    match links.len() {
+        0 if !json.is_empty() => bail!("{} only advertises JSON feeds, which are not supported", url),
+        0 => bail!("{} is neither a feed nor a page advertising one", url),
+        1 => {
+            let link = &links[0];
+            println!("found {}", link.url);
+            let data = http::get(&link.url)?;
+            let messages = Messages::new(&data).chain_err(|| format!("could not read {}", link.url))?;
+            Ok((link.url.clone(), messages.feed_name))
+        }
+        _ => {
+            let mut message = format!("{} advertises several feeds, add one of them:", url);
+            for link in &links {
+                message.push_str(&format!("\n  {}", link.url));
+                if let Some(ref title) = link.title {
+                    message.push_str(&format!(" ({})", title));
+                }
+            }
+            bail!(message)
+        }
+    }
+}
@@ -1,9 +1,20 @@
 use reqwest;
+use rusqlite;
 use std::io;
+use xml;
 
 error_chain!{
     foreign_links {
         ReqError(reqwest::Error);
         Io(io::Error);
+        Sqlite(rusqlite::Error);
+        Xml(xml::writer::Error);
    }
+
+    errors {
+        Gone(url: String) {
+            description("gone")
+            display("{} is gone", url)
+        }
+    }
 }
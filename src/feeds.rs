@@ -1,66 +1,521 @@
-use serde_json;
+use chrono::Local;
+use fs2::{self, FileExt};
+use regex::{self, Regex};
+use serde_json::{self, Value};
 use std::collections::HashSet;
-use std::fs::{OpenOptions, rename};
+use std::fmt;
+use std::fs::{self, File, OpenOptions, rename};
 use std::io::{Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
 use xdg;
+use filter::Filter;
+use message::Entry;
+use outbox::OutgoingEmail;
+use settings::{Category, DigestMode, Settings, Storage};
+use sqlite::Database;
 use errors::*;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct FeedOptions {
+    /// replace the entry body by the article downloaded from the entry URL
+    #[serde(default)]
+    pub fetch_full_content: bool,
+    /// override the digest mode of the configuration file
+    #[serde(default)]
+    pub digest: Option<DigestMode>,
+    /// override the hook script of the configuration file
+    #[serde(default)]
+    pub hook: Option<String>,
+    /// external command the new entries are piped through
+    #[serde(default)]
+    pub command: Option<String>,
+    /// override the maximum number of entries sent per run
+    #[serde(default)]
+    pub max_per_run: Option<usize>,
+    /// minutes between two fetches in daemon mode
+    #[serde(default)]
+    pub interval: Option<u64>,
+    /// override the recipient of the configuration file
+    #[serde(default)]
+    pub to: Option<String>,
+}
+
+/// Errors kept in the health of a feed.
+const MAX_ERRORS: usize = 10;
+
+/// Outcome of the last fetches of a feed.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Health {
+    /// timestamp of the last fetch
+    pub last_fetch: Option<i64>,
+    /// timestamp of the last fetch which gave a readable feed
+    pub last_success: Option<i64>,
+    /// HTTP status of the last fetch, if there was a response
+    pub last_status: Option<u16>,
+    /// error of the last failed fetch
+    pub last_error: Option<String>,
+    /// number of fetches which failed since the last success
+    pub failures: u64,
+    /// time and message of the last errors since the last success
+    pub errors: Vec<(i64, String)>,
+    /// number of entries sent, on their own or in a digest
+    pub delivered: u64,
+}
+
+impl Health {
+    pub fn success(&mut self, status: u16) {
+        let now = Local::now().timestamp();
+        self.last_fetch = Some(now);
+        self.last_success = Some(now);
+        self.last_status = Some(status);
+        self.failures = 0;
+        self.errors.clear();
+    }
+
+    pub fn failure(&mut self, status: Option<u16>, error: String) {
+        let now = Local::now().timestamp();
+        self.last_fetch = Some(now);
+        self.last_status = status;
+        self.last_error = Some(error.clone());
+        self.failures += 1;
+        if self.errors.len() >= MAX_ERRORS {
+            self.errors.remove(0);
+        }
+        self.errors.push((now, error));
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Feed {
     pub name: String,
     pub url: String,
     pub paused: bool,
+    /// why the feed was paused by rust2email, if it was
+    pub paused_reason: Option<String>,
     pub seen: HashSet<String>,
+    pub options: FeedOptions,
+    /// entries waiting for the next scheduled digest, with the feed title
+    pub pending: Vec<(String, Entry)>,
+    pub filters: Vec<Filter>,
+    /// number of entries dropped by the filters
+    pub filtered: u64,
+    /// polling interval advertised by the feed, in seconds
+    pub interval: Option<u64>,
+    /// timestamp of the next fetch in daemon mode
+    pub next_fetch: Option<i64>,
+    /// folder of the feed, nested categories are separated by `/`
+    pub category: Option<String>,
+    pub health: Health,
+    /// the seen, pending and queued entries may still be identified by
+    /// their link, as RSS entries were before version 4
+    pub link_ids: bool,
+}
+
+/// Settings of a feed which can be changed with `Feed::set`.
+pub const SETTINGS: &[&str] = &["paused", "full", "digest", "hook", "command", "max-per-run",
+                                "interval", "to", "category"];
+
+fn parse_bool(key: &str, value: &str) -> Result<bool> {
+    match value {
+        "true" | "yes" | "1" => Ok(true),
+        "false" | "no" | "0" | "" => Ok(false),
+        _ => bail!("invalid {} {}, expected true or false", key, value),
+    }
+}
+
+/// `None` for an empty value, which unsets the setting.
+fn optional(value: &str) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+impl Feed {
+    /// Change a setting of the feed, an empty value gives back the default.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let options = &mut self.options;
+        match key {
+            "paused" => {
+                self.paused = parse_bool(key, value)?;
+                self.paused_reason = None;
+            }
+            "full" => options.fetch_full_content = parse_bool(key, value)?,
+            "digest" => {
+                options.digest = match optional(value) {
+                    Some(digest) => Some(DigestMode::from_str(&digest)?),
+                    None => None,
+                }
+            }
+            "hook" => options.hook = optional(value),
+            "command" => options.command = optional(value),
+            "max-per-run" => {
+                options.max_per_run = match optional(value) {
+                    Some(max) => Some(max.parse().chain_err(|| format!("invalid max-per-run {}", max))?),
+                    None => None,
+                }
+            }
+            "interval" => {
+                options.interval = match optional(value) {
+                    Some(interval) => {
+                        Some(interval.parse().chain_err(|| format!("invalid interval {}", interval))?)
+                    }
+                    None => None,
+                }
+            }
+            "to" => options.to = optional(value),
+            "category" => self.category = optional(value),
+            _ => bail!("unknown setting {}, expected one of {}", key, SETTINGS.join(", ")),
+        }
+        Ok(())
+    }
+
+    fn category<'a>(&self, settings: &'a Settings) -> Option<&'a Category> {
+        self.category.as_ref().and_then(|category| settings.category(category))
+    }
+
+    /// Digest mode from the feed options, its category or the configuration file.
+    pub fn digest(&self, settings: &Settings) -> DigestMode {
+        self.options
+            .digest
+            .or(self.category(settings).and_then(|category| category.digest))
+            .unwrap_or(settings.digest)
+    }
+
+    /// Recipient from the feed options or its category, the one of the
+    /// configuration file is used otherwise.
+    pub fn to<'a>(&'a self, settings: &'a Settings) -> Option<&'a String> {
+        self.options.to.as_ref().or(self.category(settings).and_then(|category| category.to.as_ref()))
+    }
+
+    /// Extra email headers of the feed category.
+    pub fn headers<'a>(&self, settings: &'a Settings) -> &'a [(String, String)] {
+        match self.category(settings) {
+            Some(category) => &category.headers,
+            None => &[],
+        }
+    }
+
+    /// Whether `entry` was sent or dropped by the filters.
+    pub fn has_seen(&self, entry: &Entry) -> bool {
+        self.seen.contains(&entry.id) || self.link_ids && self.seen.contains(&entry.url)
+    }
+
+    pub fn is_pending(&self, entry: &Entry) -> bool {
+        self.pending
+            .iter()
+            .any(|&(_, ref pending)| {
+                     pending.id == entry.id || self.link_ids && pending.id == entry.url
+                 })
+    }
+
+}
+
+/// Version of the database written by this rust2email.
+///
+/// Any change of the stored data, new fields included, bumps the version
+/// and comes with a migration filling in the new fields, which have no
+/// serde default.
+const VERSION: u32 = 4;
+
+/// Migrations from each version of the database to the next one, the
+/// first one upgrades version 1 to version 2.
+const MIGRATIONS: &[fn(&mut Value)] = &[migrate_v2, migrate_v3, migrate_v4];
+
+/// Version 1 databases may lack the fields added since, which are given
+/// their default value.
+fn migrate_v2(data: &mut Value) {
+    if let Some(data) = data.as_object_mut() {
+        data.entry("last_digest").or_insert(Value::Null);
+        data.entry("outbox").or_insert(json!([]));
+        if let Some(feeds) = data.get_mut("feeds").and_then(Value::as_array_mut) {
+            for feed in feeds.iter_mut().filter_map(Value::as_object_mut) {
+                feed.entry("options").or_insert(json!({}));
+                feed.entry("pending").or_insert(json!([]));
+                feed.entry("filters").or_insert(json!([]));
+                feed.entry("filtered").or_insert(json!(0));
+                feed.entry("interval").or_insert(Value::Null);
+                feed.entry("next_fetch").or_insert(Value::Null);
+            }
+        }
+    }
+}
+
+/// Version 3 adds the category, the reason why a feed was paused and the
+/// health of the feeds.
+fn migrate_v3(data: &mut Value) {
+    if let Some(feeds) = data.get_mut("feeds").and_then(Value::as_array_mut) {
+        for feed in feeds.iter_mut().filter_map(Value::as_object_mut) {
+            feed.entry("category").or_insert(Value::Null);
+            feed.entry("paused_reason").or_insert(Value::Null);
+            if let Some(health) = feed.entry("health").or_insert(json!({})).as_object_mut() {
+                health.entry("last_fetch").or_insert(Value::Null);
+                health.entry("last_success").or_insert(Value::Null);
+                health.entry("last_status").or_insert(Value::Null);
+                health.entry("last_error").or_insert(Value::Null);
+                health.entry("failures").or_insert(json!(0));
+                health.entry("errors").or_insert(json!([]));
+                health.entry("delivered").or_insert(json!(0));
+            }
+        }
+    }
+}
+
+/// RSS entries were identified by their link until version 4, they are
+/// now identified by their guid.
+fn migrate_v4(data: &mut Value) {
+    if let Some(feeds) = data.get_mut("feeds").and_then(Value::as_array_mut) {
+        for feed in feeds.iter_mut().filter_map(Value::as_object_mut) {
+            feed.insert("link_ids".into(), json!(true));
+        }
+    }
+}
+
+/// How long to wait for another instance to release the database.
+const LOCK_TIMEOUT: u64 = 60;
+
+/// Advisory lock on the database, released when dropped.
+///
+/// The lock is taken on a separate file since the database is replaced
+/// at each save.
+#[derive(Debug)]
+struct Lock {
+    file: File,
+}
+
+impl Lock {
+    fn new(data_file: &Path) -> Result<Self> {
+        let mut lock_file = data_file.as_os_str().to_owned();
+        lock_file.push(".lock");
+        let lock_file = PathBuf::from(lock_file);
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .open(&lock_file)
+            .chain_err(|| format!("could not open {}", lock_file.display()))?;
+
+        let start = Instant::now();
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => return Ok(Lock { file: file }),
+                Err(ref e) if e.kind() == fs2::lock_contended_error().kind() => {
+                    if start.elapsed() > Duration::from_secs(LOCK_TIMEOUT) {
+                        bail!("{} is locked by another rust2email", data_file.display());
+                    }
+                    thread::sleep(Duration::from_millis(100));
+                }
+                Err(e) => {
+                    return Err(e).chain_err(|| format!("could not lock {}", lock_file.display()))
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+/// Where the database is stored.
+enum Store {
+    Json,
+    Sqlite(Database),
+}
+
+impl Default for Store {
+    fn default() -> Self {
+        Store::Json
+    }
+}
+
+impl fmt::Debug for Store {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Store::Json => write!(f, "Json"),
+            &Store::Sqlite(_) => write!(f, "Sqlite"),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Feeds {
     pub version: u32,
     pub feeds: Vec<Feed>,
+    /// timestamp of the last scheduled digest
+    pub last_digest: Option<i64>,
+    /// emails which could not be delivered yet
+    pub outbox: Vec<OutgoingEmail>,
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    store: Store,
+    #[serde(skip)]
+    lock: Option<Lock>,
 }
 
 impl Feeds {
-    pub fn new(path: Option<&str>) -> Result<Self> {
-        let xdg_dirs = xdg::BaseDirectories::with_prefix("rust2email").unwrap();
-        let data_file = match path {
+    fn data_file(path: Option<&str>, storage: Storage) -> PathBuf {
+        match path {
             Some(path) => path.into(),
-            None => xdg_dirs.place_data_file("rust2email.json").unwrap(),
+            None => {
+                let xdg_dirs = xdg::BaseDirectories::with_prefix("rust2email").unwrap();
+                let name = match storage {
+                    Storage::Json => "rust2email.json",
+                    Storage::Sqlite => "rust2email.sqlite",
+                };
+                xdg_dirs.place_data_file(name).unwrap()
+            }
+        }
+    }
+
+    /// Load the database, which stays locked until it is dropped.
+    pub fn new(path: Option<&str>, storage: Storage) -> Result<Self> {
+        let data_file = Feeds::data_file(path, storage);
+        let lock = Lock::new(&data_file)?;
+
+        let mut feeds = match storage {
+            Storage::Json => Feeds::read(&data_file)?,
+            Storage::Sqlite => {
+                let json_file = data_file.with_extension("json");
+                if !data_file.exists() && json_file.exists() {
+                    Feeds::migrate(&json_file, &data_file)?;
+                }
+                let mut database = Database::open(&data_file, VERSION)?;
+                let mut feeds = Feeds::parse(database.load()?, &data_file)?;
+                feeds.store = Store::Sqlite(database);
+                feeds
+            }
+        };
+        feeds.path = data_file;
+        feeds.lock = Some(lock);
+        Ok(feeds)
+    }
+
+    /// One-shot migration from the JSON database to SQLite.
+    ///
+    /// The SQLite file is built under a temporary name, so that a failed
+    /// migration leaves nothing behind and is tried again on the next run.
+    fn migrate(json_file: &Path, data_file: &Path) -> Result<()> {
+        let _lock = Lock::new(json_file)?;
+        let mut feeds = Feeds::read(json_file)?;
+        let mut temp_file = data_file.as_os_str().to_owned();
+        temp_file.push(".new");
+        let temp_file = PathBuf::from(temp_file);
+        // left over by an interrupted migration
+        let _ = fs::remove_file(&temp_file);
+        let saved = Database::open(&temp_file, VERSION).and_then(|mut database| {
+            database.save(feeds.version, &mut feeds.feeds, feeds.last_digest, &feeds.outbox)
+        });
+        if let Err(e) = saved {
+            let _ = fs::remove_file(&temp_file);
+            return Err(e);
+        }
+        rename(&temp_file, data_file)?;
+        let mut migrated = json_file.as_os_str().to_owned();
+        migrated.push(".migrated");
+        rename(json_file, &migrated)?;
+        println!("migrated {} to {}", json_file.display(), data_file.display());
+        Ok(())
+    }
+
+    /// Location of the database.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Load the database again, keeping the lock.
+    pub fn reload(&mut self) -> Result<()> {
+        let feeds = match self.store {
+            Store::Json => Feeds::read(&self.path)?,
+            Store::Sqlite(ref mut database) => Feeds::parse(database.load()?, &self.path)?,
         };
+        self.version = feeds.version;
+        self.feeds = feeds.feeds;
+        self.last_digest = feeds.last_digest;
+        self.outbox = feeds.outbox;
+        Ok(())
+    }
 
+    pub fn lock(&mut self) -> Result<()> {
+        if self.lock.is_none() {
+            self.lock = Some(Lock::new(&self.path)?);
+        }
+        Ok(())
+    }
+
+    /// Let another instance use the database, `lock` must be called
+    /// before saving it.
+    pub fn unlock(&mut self) {
+        self.lock = None;
+    }
+
+    fn read(data_file: &Path) -> Result<Self> {
         let mut f = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
             .open(data_file)
-            .unwrap();
+            .chain_err(|| format!("could not open {}", data_file.display()))?;
 
-        let mut config = Feeds {
-            version: 1,
-            feeds: Vec::new(),
-        };
+        if f.metadata()?.len() == 0 {
+            return Ok(Feeds {
+                          version: VERSION,
+                          feeds: Vec::new(),
+                          last_digest: None,
+                          outbox: Vec::new(),
+                          path: PathBuf::new(),
+                          store: Store::Json,
+                          lock: None,
+                      });
+        }
+
+        let mut data = String::new();
+        f.read_to_string(&mut data)?;
 
-        if f.metadata().unwrap().len() != 0 {
-            let mut data = String::new();
-            f.read_to_string(&mut data).unwrap();
+        let data: Value = serde_json::from_str(data.as_str())
+            .chain_err(|| format!("could not parse {}", data_file.display()))?;
+        Feeds::parse(data, data_file)
+    }
 
-            config = serde_json::from_str(data.as_str()).unwrap();
+    /// Upgrade the database if it comes from an older version.
+    fn parse(mut data: Value, data_file: &Path) -> Result<Self> {
+        let version = data.get("version").and_then(Value::as_u64).unwrap_or(1).max(1) as u32;
+        if version > VERSION {
+            bail!("{} was written by a newer rust2email (database version {}, this one \
+                   supports up to {})",
+                  data_file.display(),
+                  version,
+                  VERSION);
+        }
+        if version < VERSION {
+            // keep the previous file in case something goes wrong
+            let mut backup = data_file.as_os_str().to_owned();
+            backup.push(format!(".v{}", version));
+            fs::copy(data_file, &backup)
+                .chain_err(|| format!("could not back up {}", data_file.display()))?;
+            for migration in &MIGRATIONS[version as usize - 1..] {
+                migration(&mut data);
+            }
+            data["version"] = json!(VERSION);
         }
 
-        Ok(config)
+        serde_json::from_value(data).chain_err(|| format!("could not parse {}", data_file.display()))
     }
 
-    pub fn save(&self, path: Option<&str>) -> Result<()> {
-        let xdg_dirs = xdg::BaseDirectories::with_prefix("rust2email").unwrap();
-        let (data_file, data_file_tmp) = match path {
-            Some(path) => {
-                let data_tmp = format!("{}.new", &path);
-                (path.into(), data_tmp.into())
-            }
-            None => {
-                (xdg_dirs.place_data_file("rust2email.json").unwrap(),
-                 xdg_dirs.place_data_file("rust2email.json.new").unwrap())
-            }
-        };
+    pub fn save(&mut self) -> Result<()> {
+        if let Store::Sqlite(ref mut database) = self.store {
+            return database.save(self.version, &mut self.feeds, self.last_digest, &self.outbox);
+        }
+
+        let mut data_file_tmp = self.path.as_os_str().to_owned();
+        data_file_tmp.push(".new");
 
         {
             let mut f = OpenOptions::new()
@@ -68,19 +523,123 @@ impl Feeds {
                 .create(true)
                 .truncate(true)
                 .open(&data_file_tmp)
-                .unwrap();
+                .chain_err(|| format!("could not write {}", self.path.display()))?;
 
             let data = serde_json::to_string_pretty(&self).unwrap();
 
-            f.write(data.as_bytes()).unwrap();
+            f.write_all(data.as_bytes())?;
         }
 
-        rename(data_file_tmp, data_file).unwrap();
+        rename(data_file_tmp, &self.path)?;
 
         Ok(())
 
     }
 
+    /// Indexes of the feeds given by `selectors`, each one is an index, a
+    /// range of indexes such as `3-7`, a name, a glob matching names or a
+    /// part of the URL.
+    ///
+    /// All the selectors are checked before anything is returned, so that
+    /// nothing is changed when one of them is wrong.
+    pub fn select(&self, selectors: &[&str]) -> Result<Vec<usize>> {
+        let mut indexes = Vec::new();
+        let mut unknown = Vec::new();
+        for selector in selectors {
+            if selector.trim().is_empty() {
+                bail!("empty feed selector");
+            }
+            let selected = self.select_all(selector);
+            if selected.is_empty() {
+                unknown.push(*selector);
+            }
+            indexes.extend(selected);
+        }
+        if !unknown.is_empty() {
+            bail!("no feed matches {}", unknown.join(", "));
+        }
+        indexes.sort();
+        indexes.dedup();
+        Ok(indexes)
+    }
+
+    /// Index of the feed given by `selector`, which must only match one.
+    pub fn select_one(&self, selector: &str) -> Result<usize> {
+        // it would match every URL
+        if selector.trim().is_empty() {
+            bail!("empty feed selector");
+        }
+        let selected = self.select_all(selector);
+        match selected.len() {
+            0 => bail!("no feed matches {}", selector),
+            1 => Ok(selected[0]),
+            _ => {
+                let names: Vec<&str> = selected
+                    .iter()
+                    .map(|&index| self.feeds[index].name.as_str())
+                    .collect();
+                bail!("{} matches several feeds: {}", selector, names.join(", "))
+            }
+        }
+    }
+
+    fn select_all(&self, selector: &str) -> Vec<usize> {
+        let len = self.feeds.len();
+        // a feed named like an index is still selected by its name
+        if let Some(index) = self.feeds.iter().position(|feed| feed.name == selector) {
+            return vec![index];
+        }
+        if let Ok(index) = selector.parse::<usize>() {
+            return if index < len { vec![index] } else { Vec::new() };
+        }
+        let range: Vec<&str> = selector.splitn(2, '-').collect();
+        if range.len() == 2 {
+            if let (Ok(start), Ok(end)) = (range[0].trim().parse::<usize>(),
+                                           range[1].trim().parse::<usize>()) {
+                return if start <= end && end < len {
+                           (start..end + 1).collect()
+                       } else {
+                           Vec::new()
+                       };
+            }
+        }
+        if selector.contains(|c| c == '*' || c == '?') {
+            let pattern = selector
+                .split('*')
+                .map(|part| {
+                         part.split('?')
+                             .map(|part| regex::escape(part))
+                             .collect::<Vec<_>>()
+                             .join(".")
+                     })
+                .collect::<Vec<_>>()
+                .join(".*");
+            return match Regex::new(&format!("^{}$", pattern)) {
+                       Ok(glob) => {
+                           (0..len).filter(|&index| glob.is_match(&self.feeds[index].name)).collect()
+                       }
+                       Err(_) => Vec::new(),
+                   };
+        }
+        (0..len).filter(|&index| self.feeds[index].url.contains(selector)).collect()
+    }
+
+    /// Rename a feed, along with its emails waiting in the outbox.
+    pub fn rename(&mut self, index: usize, name: &str) -> Result<()> {
+        if self.contains(name) {
+            bail!("{} already exists", name);
+        }
+        let old = mem::replace(&mut self.feeds[index].name, name.to_string());
+        for email in &mut self.outbox {
+            for &mut (ref mut feed, _) in &mut email.entries {
+                if *feed == old {
+                    *feed = name.to_string();
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn contains(&self, name: &str) -> bool {
         for ref feed in &self.feeds {
             if feed.name == name {
@@ -90,13 +649,128 @@ impl Feeds {
         return false;
     }
 
-    pub fn push(&mut self, name: &str, url: &str) {
+    /// Mark entries, given by feed name and id, as seen.
+    pub fn mark_seen(&mut self, entries: &[(String, String)]) {
+        for &(ref name, ref id) in entries {
+            if let Some(feed) = self.feeds.iter_mut().find(|feed| &feed.name == name) {
+                feed.seen.insert(id.clone());
+            }
+        }
+    }
+
+    /// Mark entries, given by feed name and id, as seen once they are sent.
+    pub fn delivered(&mut self, entries: &[(String, String)]) {
+        self.mark_seen(entries);
+        for &(ref name, _) in entries {
+            if let Some(feed) = self.feeds.iter_mut().find(|feed| &feed.name == name) {
+                feed.health.delivered += 1;
+            }
+        }
+    }
+
+    pub fn push(&mut self, name: &str, url: &str) -> &mut Feed {
         self.feeds
             .push(Feed {
                       name: name.to_string(),
                       url: url.to_string(),
                       paused: false,
+                      paused_reason: None,
                       seen: HashSet::new(),
+                      options: FeedOptions::default(),
+                      pending: Vec::new(),
+                      filters: Vec::new(),
+                      filtered: 0,
+                      interval: None,
+                      next_fetch: None,
+                      category: None,
+                      health: Health::default(),
+                      link_ids: false,
                   });
+        self.feeds.last_mut().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feeds(names: &[(&str, &str)]) -> Feeds {
+        let mut feeds = Feeds {
+            version: VERSION,
+            feeds: Vec::new(),
+            last_digest: None,
+            outbox: Vec::new(),
+            path: PathBuf::new(),
+            store: Store::Json,
+            lock: None,
+        };
+        for &(name, url) in names {
+            feeds.push(name, url);
+        }
+        feeds
+    }
+
+    fn sample() -> Feeds {
+        feeds(&[("rust-news", "https://blog.rust-lang.org/feed.xml"),
+                ("rust-users", "https://users.rust-lang.org/latest.rss"),
+                ("2024", "https://example.org/2024.xml"),
+                ("c++ (weekly)", "https://isocpp.org/blog/rss"),
+                ("1", "https://example.org/one.xml")])
+    }
+
+    #[test]
+    fn select_by_index_and_range() {
+        let feeds = sample();
+        assert_eq!(feeds.select_all("0"), vec![0]);
+        assert_eq!(feeds.select_all("5"), Vec::<usize>::new());
+        assert_eq!(feeds.select_all("1-3"), vec![1, 2, 3]);
+        assert_eq!(feeds.select_all("2-2"), vec![2]);
+        assert_eq!(feeds.select_all("3-1"), Vec::<usize>::new());
+        assert_eq!(feeds.select_all("3-5"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn select_by_name_before_index_and_range() {
+        let feeds = sample();
+        assert_eq!(feeds.select_all("2024"), vec![2]);
+        assert_eq!(feeds.select_all("1"), vec![4]);
+        assert_eq!(feeds.select_all("rust-news"), vec![0]);
+    }
+
+    #[test]
+    fn select_by_glob() {
+        let feeds = sample();
+        assert_eq!(feeds.select_all("rust-*"), vec![0, 1]);
+        assert_eq!(feeds.select_all("rust-?ews"), vec![0]);
+        assert_eq!(feeds.select_all("*"), vec![0, 1, 2, 3, 4]);
+        // the other characters of a glob are not a regular expression
+        assert_eq!(feeds.select_all("c++ (*)"), vec![3]);
+        assert_eq!(feeds.select_all("rust.*"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn select_by_url() {
+        let feeds = sample();
+        assert_eq!(feeds.select_all("rust-lang.org"), vec![0, 1]);
+        assert_eq!(feeds.select_all("isocpp"), vec![3]);
+        assert_eq!(feeds.select_all("unknown"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn select_several() {
+        let feeds = sample();
+        assert_eq!(feeds.select(&["1-2", "rust-*", "isocpp"]).ok(), Some(vec![0, 1, 2, 3]));
+        assert!(feeds.select(&["0", "unknown"]).is_err());
+        assert!(feeds.select_one("rust-*").is_err());
+        assert_eq!(feeds.select_one("isocpp").ok(), Some(3));
+    }
+
+    #[test]
+    fn select_nothing_with_blank_selectors() {
+        let feeds = feeds(&[("only", "https://example.org/feed.xml")]);
+        assert!(feeds.select(&[""]).is_err());
+        assert!(feeds.select(&["0", " "]).is_err());
+        assert!(feeds.select_one("").is_err());
+        assert!(feeds.select_one("\t").is_err());
     }
 }
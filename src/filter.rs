@@ -0,0 +1,173 @@
+use regex::{Regex, RegexBuilder};
+use message::Entry;
+use errors::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterAction {
+    /// only mail the entries matching one of the include filters
+    Include,
+    /// never mail the entries matching an exclude filter
+    Exclude,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterField {
+    Any,
+    Title,
+    Body,
+    Author,
+    Categories,
+    Url,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Filter {
+    pub action: FilterAction,
+    pub field: FilterField,
+    pub pattern: String,
+    /// the pattern is a regular expression instead of a keyword
+    #[serde(default)]
+    pub regex: bool,
+}
+
+enum Matcher {
+    Regex(Regex),
+    Keyword(String),
+}
+
+impl Matcher {
+    fn is_match(&self, text: &str) -> bool {
+        match self {
+            &Matcher::Regex(ref regex) => regex.is_match(text),
+            &Matcher::Keyword(ref keyword) => text.to_lowercase().contains(keyword),
+        }
+    }
+
+    fn matches(&self, field: FilterField, entry: &Entry) -> bool {
+        match field {
+            FilterField::Title => self.is_match(&entry.title),
+            FilterField::Body => self.is_match(&entry.body),
+            FilterField::Author => self.is_match(&entry.author),
+            FilterField::Categories => {
+                entry
+                    .categories
+                    .iter()
+                    .any(|category| self.is_match(category))
+            }
+            FilterField::Url => self.is_match(&entry.url),
+            FilterField::Any => {
+                [FilterField::Title,
+                 FilterField::Body,
+                 FilterField::Author,
+                 FilterField::Categories,
+                 FilterField::Url]
+                        .iter()
+                        .any(|&field| self.matches(field, entry))
+            }
+        }
+    }
+}
+
+impl FilterAction {
+    pub fn from_str(action: &str) -> Result<Self> {
+        match action {
+            "include" => Ok(FilterAction::Include),
+            "exclude" => Ok(FilterAction::Exclude),
+            _ => bail!("unknown filter action {}, expected include or exclude", action),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            FilterAction::Include => "include",
+            FilterAction::Exclude => "exclude",
+        }
+    }
+}
+
+impl FilterField {
+    pub fn from_str(field: &str) -> Result<Self> {
+        match field {
+            "any" => Ok(FilterField::Any),
+            "title" => Ok(FilterField::Title),
+            "body" => Ok(FilterField::Body),
+            "author" => Ok(FilterField::Author),
+            "categories" => Ok(FilterField::Categories),
+            "url" => Ok(FilterField::Url),
+            _ => bail!("unknown filter field {}, expected any, title, body, author, categories or url", field),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            FilterField::Any => "any",
+            FilterField::Title => "title",
+            FilterField::Body => "body",
+            FilterField::Author => "author",
+            FilterField::Categories => "categories",
+            FilterField::Url => "url",
+        }
+    }
+}
+
+impl Filter {
+    pub fn new(action: FilterAction, field: FilterField, pattern: &str, regex: bool) -> Result<Self> {
+        let filter = Filter {
+            action: action,
+            field: field,
+            pattern: pattern.to_string(),
+            regex: regex,
+        };
+        // refuse invalid regular expressions now rather than at each run
+        filter.matcher()?;
+        Ok(filter)
+    }
+
+    fn matcher(&self) -> Result<Matcher> {
+        if self.regex {
+            match RegexBuilder::new(&self.pattern).case_insensitive(true).build() {
+                Ok(regex) => Ok(Matcher::Regex(regex)),
+                Err(e) => bail!("invalid filter {}: {}", self.pattern, e),
+            }
+        } else {
+            Ok(Matcher::Keyword(self.pattern.to_lowercase()))
+        }
+    }
+}
+
+/// Filters of a feed, compiled once to check all of its entries.
+pub struct Filters {
+    filters: Vec<(FilterAction, FilterField, Matcher)>,
+}
+
+impl Filters {
+    pub fn new(filters: &[Filter]) -> Result<Self> {
+        let mut compiled = Vec::new();
+        for filter in filters {
+            compiled.push((filter.action, filter.field, filter.matcher()?));
+        }
+        Ok(Filters { filters: compiled })
+    }
+
+    /// Whether `entry` should be mailed.
+    pub fn accept(&self, entry: &Entry) -> bool {
+        let mut included = None;
+        for &(action, field, ref matcher) in &self.filters {
+            match action {
+                FilterAction::Exclude => {
+                    if matcher.matches(field, entry) {
+                        return false;
+                    }
+                }
+                FilterAction::Include => {
+                    if included != Some(true) {
+                        included = Some(matcher.matches(field, entry));
+                    }
+                }
+            }
+        }
+        included.unwrap_or(true)
+    }
+}
@@ -0,0 +1,111 @@
+#[cfg(feature = "hook")]
+use std::path::PathBuf;
+#[cfg(feature = "hook")]
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+use message::Entry;
+use errors::*;
+
+/// A user script run on each new entry before its email is built.
+///
+/// The script sees the entry as the variables `feed`, `id`, `title`,
+/// `url`, `body`, `author`, `categories`, `to` and `headers`, and can
+/// modify them or set `skip` to `true` to drop the entry.
+#[cfg(feature = "hook")]
+pub struct Hook {
+    engine: Engine,
+    ast: AST,
+}
+
+#[cfg(feature = "hook")]
+fn get_string(scope: &Scope, name: &str) -> Result<String> {
+    match scope.get_value::<String>(name) {
+        Some(value) => Ok(value),
+        None => bail!("hook variable {} must be a string", name),
+    }
+}
+
+#[cfg(feature = "hook")]
+impl Hook {
+    pub fn new(path: &str) -> Result<Self> {
+        let engine = Engine::new();
+        match engine.compile_file(PathBuf::from(path)) {
+            Ok(ast) => {
+                Ok(Hook {
+                       engine: engine,
+                       ast: ast,
+                   })
+            }
+            Err(e) => bail!("could not load hook {}: {}", path, e),
+        }
+    }
+
+    /// Run the script on `entry`, `None` when the script dropped it.
+    pub fn apply(&self, feed_name: &str, mut entry: Entry) -> Result<Option<Entry>> {
+        let mut scope = Scope::new();
+        let categories: Array = entry
+            .categories
+            .iter()
+            .map(|category| Dynamic::from(category.clone()))
+            .collect();
+        let mut headers = Map::new();
+        for &(ref name, ref value) in &entry.headers {
+            headers.insert(name.as_str().into(), Dynamic::from(value.clone()));
+        }
+        scope.push("feed", feed_name.to_string());
+        scope.push("id", entry.id.clone());
+        scope.push("title", entry.title.clone());
+        scope.push("url", entry.url.clone());
+        scope.push("body", entry.body.clone());
+        scope.push("author", entry.author.clone());
+        scope.push("categories", categories);
+        scope.push("to", entry.to.clone().unwrap_or_default());
+        scope.push("headers", headers);
+        scope.push("skip", false);
+
+        if let Err(e) = self.engine.run_ast_with_scope(&mut scope, &self.ast) {
+            bail!("hook failed: {}", e);
+        }
+
+        if scope.get_value::<bool>("skip").unwrap_or(false) {
+            return Ok(None);
+        }
+
+        entry.title = get_string(&scope, "title")?;
+        entry.url = get_string(&scope, "url")?;
+        entry.body = get_string(&scope, "body")?;
+        entry.author = get_string(&scope, "author")?;
+        entry.categories = match scope.get_value::<Array>("categories") {
+            Some(categories) => categories.into_iter().map(|c| c.to_string()).collect(),
+            None => bail!("hook variable categories must be an array"),
+        };
+        let to = get_string(&scope, "to")?;
+        entry.to = if to.is_empty() { None } else { Some(to) };
+        entry.headers = match scope.get_value::<Map>("headers") {
+            Some(headers) => {
+                headers
+                    .into_iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect()
+            }
+            None => bail!("hook variable headers must be a map"),
+        };
+
+        Ok(Some(entry))
+    }
+}
+
+/// Stand-in when rust2email is built without the `hook` feature.
+#[cfg(not(feature = "hook"))]
+pub struct Hook;
+
+#[cfg(not(feature = "hook"))]
+impl Hook {
+    pub fn new(path: &str) -> Result<Self> {
+        bail!("could not load hook {}: rust2email was built without the hook feature",
+              path)
+    }
+
+    pub fn apply(&self, _feed_name: &str, entry: Entry) -> Result<Option<Entry>> {
+        Ok(Some(entry))
+    }
+}
@@ -5,22 +5,216 @@ use encoding::codec::utf_8::UTF8Encoding;
 use encoding::types::DecoderTrap;
 use encoding::Encoding;
 
-pub fn get_feed(url: &str) -> Result<String> {
-    match reqwest::get(url) {
+/// A downloaded document.
+pub struct Document {
+    pub status: reqwest::StatusCode,
+    pub content_type: Option<String>,
+    pub text: String,
+    /// new location of the document, when it moved permanently
+    pub moved_to: Option<String>,
+}
+
+impl Document {
+    /// Encoding given by the `Content-Type` header, or else by the XML
+    /// declaration.
+    pub fn encoding(&self) -> Option<String> {
+        if let Some(ref content_type) = self.content_type {
+            // lowercased as ASCII only, for the positions to stay the same
+            if let Some(position) = content_type.to_ascii_lowercase().find("charset=") {
+                let charset = content_type[position + "charset=".len()..]
+                    .split(';')
+                    .next()
+                    .unwrap_or("")
+                    .trim()
+                    .trim_matches('"');
+                if !charset.is_empty() {
+                    return Some(charset.to_string());
+                }
+            }
+        }
+        let text = self.text.trim_start();
+        if !text.starts_with("<?xml") {
+            return None;
+        }
+        let declaration = &text[..text.find("?>").unwrap_or(0)];
+        let encoding = declaration.split("encoding=").nth(1)?;
+        let quote = encoding.chars().next()?;
+        if quote != '"' && quote != '\'' {
+            return None;
+        }
+        match encoding.trim_start_matches(quote).split(quote).next() {
+            Some(encoding) if !encoding.is_empty() => Some(encoding.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Redirections followed before giving up.
+const MAX_REDIRECTS: usize = 10;
+
+/// Download `url`, following the redirections.
+///
+/// A document which only went through permanent redirections (301 and
+/// 308) has its new location in `moved_to`. A 410 Gone response is
+/// reported with `ErrorKind::Gone`.
+pub fn fetch(url: &str) -> Result<Document> {
+    let client = reqwest::Client::builder()?
+        .redirect(reqwest::RedirectPolicy::none())
+        .build()?;
+    let mut url = reqwest::Url::parse(url).chain_err(|| format!("invalid URL {}", url))?;
+    let mut moved_to = None;
+    let mut permanent = true;
+    let mut redirects = 0;
+    let resp = loop {
+        let resp = client.get(url.clone())?.send()?;
+        let status = resp.status();
+        if !status.is_redirection() {
+            break resp;
+        }
+        let location = match resp.headers().get::<reqwest::header::Location>() {
+            Some(location) => location.to_string(),
+            None => break resp,
+        };
+        redirects += 1;
+        if redirects > MAX_REDIRECTS {
+            bail!("too many redirections from {}", url);
+        }
+        url = url.join(&location)
+            .chain_err(|| format!("invalid redirection to {}", location))?;
+        permanent = permanent &&
+                    (status == reqwest::StatusCode::MovedPermanently ||
+                     status == reqwest::StatusCode::PermanentRedirect);
+        if permanent {
+            moved_to = Some(url.to_string());
+        }
+    };
+    if resp.status() == reqwest::StatusCode::Gone {
+        bail!(ErrorKind::Gone(url.to_string()));
+    }
+    match resp.error_for_status() {
         Err(err) => Err(err.into()),
         Ok(resp) => {
-            match resp.error_for_status() {
-                Err(err) => Err(err.into()),
-                Ok(resp) => {
-                    let bytes: Vec<u8> = resp.bytes()
-                        .map(|res| res.unwrap())
-                        .collect();
-                    match UTF8Encoding.decode(bytes.as_slice(), DecoderTrap::Replace) {
-                        Ok(string) => Ok(string),
-                        Err(err) => Err(err.to_string().into())
-                    }
+            let status = resp.status();
+            let content_type = resp.headers()
+                .get::<reqwest::header::ContentType>()
+                .map(|content_type| content_type.to_string());
+            let bytes: Vec<u8> = resp.bytes()
+                .map(|res| res.unwrap())
+                .collect();
+            match UTF8Encoding.decode(bytes.as_slice(), DecoderTrap::Replace) {
+                Ok(string) => {
+                    Ok(Document {
+                           status: status,
+                           content_type: content_type,
+                           text: string,
+                           moved_to: moved_to,
+                       })
                 }
+                Err(err) => Err(err.to_string().into())
             }
         }
     }
 }
+
+/// HTTP status of the response which caused `err`, if any.
+pub fn status(err: &Error) -> Option<u16> {
+    match *err.kind() {
+        ErrorKind::Gone(_) => Some(410),
+        ErrorKind::ReqError(ref err) => err.status().map(|status| status.as_u16()),
+        _ => None,
+    }
+}
+
+/// Text of the document at `url`.
+pub fn get(url: &str) -> Result<String> {
+    fetch(url).map(|document| document.text)
+}
+
+/// Key identifying the location of a feed whatever the way its URL is
+/// written: scheme, case of the host, default port, fragment and trailing
+/// slash are ignored.
+pub fn normalize(url: &str) -> String {
+    match reqwest::Url::parse(url.trim()) {
+        Ok(url) => {
+            let mut normalized = format!("{}{}",
+                                         url.host_str().unwrap_or(""),
+                                         match url.port() {
+                                             Some(port) => format!(":{}", port),
+                                             None => String::new(),
+                                         });
+            normalized.push_str(url.path().trim_end_matches('/'));
+            if let Some(query) = url.query() {
+                normalized.push('?');
+                normalized.push_str(query);
+            }
+            normalized
+        }
+        Err(_) => url.trim().trim_end_matches('/').to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_ignores_scheme_case_and_default_port() {
+        let url = normalize("https://example.org/feed.xml");
+        assert_eq!(normalize("http://example.org/feed.xml"), url);
+        assert_eq!(normalize("HTTPS://Example.ORG/feed.xml"), url);
+        assert_eq!(normalize("https://example.org:443/feed.xml"), url);
+        assert_eq!(normalize("  https://example.org/feed.xml "), url);
+    }
+
+    #[test]
+    fn normalize_ignores_trailing_slash_and_fragment() {
+        let url = normalize("https://example.org/blog");
+        assert_eq!(normalize("https://example.org/blog/"), url);
+        assert_eq!(normalize("https://example.org/blog#top"), url);
+        assert_eq!(normalize("https://example.org/"), normalize("https://example.org"));
+    }
+
+    #[test]
+    fn normalize_keeps_path_case_port_and_query() {
+        let url = normalize("https://example.org/feed?format=rss");
+        assert_eq!(url, "example.org/feed?format=rss");
+        assert!(normalize("https://example.org/feed?format=atom") != url);
+        assert!(normalize("https://example.org/Feed?format=rss") != url);
+        assert_eq!(normalize("http://example.org:8080/feed"), "example.org:8080/feed");
+    }
+
+    #[test]
+    fn normalize_invalid_url() {
+        assert_eq!(normalize(" not a url/ "), "not a url");
+    }
+
+    fn document(content_type: Option<&str>, text: &str) -> Document {
+        Document {
+            status: reqwest::StatusCode::Ok,
+            content_type: content_type.map(|content_type| content_type.to_string()),
+            text: text.to_string(),
+            moved_to: None,
+        }
+    }
+
+    #[test]
+    fn encoding_from_content_type() {
+        let encoding = |content_type| document(Some(content_type), "").encoding();
+        assert_eq!(encoding("text/xml; charset=ISO-8859-1"), Some("ISO-8859-1".to_string()));
+        assert_eq!(encoding("text/xml; Charset=\"utf-8\"; x=y"), Some("utf-8".to_string()));
+        assert_eq!(encoding("text/xml; charset="), None);
+        assert_eq!(encoding("text/xml"), None);
+    }
+
+    #[test]
+    fn encoding_from_xml_declaration() {
+        let encoding = |text| document(None, text).encoding();
+        assert_eq!(encoding("<?xml version=\"1.0\" encoding=\"windows-1252\"?><rss/>"),
+                   Some("windows-1252".to_string()));
+        assert_eq!(encoding("<?xml version='1.0' encoding='utf-8'?>"), Some("utf-8".to_string()));
+        assert_eq!(encoding("<?xml version=\"1.0\" encoding=?><rss/>"), None);
+        assert_eq!(encoding("<?xml version=\"1.0\" encoding=é?><rss/>"), None);
+        assert_eq!(encoding("<?xml version=\"1.0\" encoding=\"\"?>"), None);
+        assert_eq!(encoding("<rss/>"), None);
+    }
+}
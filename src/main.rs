@@ -1,17 +1,26 @@
 extern crate atom_syndication;
+extern crate chrono;
 #[macro_use]
 extern crate clap;
+extern crate ctrlc;
 extern crate encoding;
 #[macro_use]
 extern crate error_chain;
+extern crate fs2;
 extern crate html2text;
+extern crate html5ever;
 extern crate lettre;
 extern crate lettre_email;
+extern crate regex;
 extern crate reqwest;
+#[cfg(feature = "hook")]
+extern crate rhai;
 extern crate rss;
+extern crate rusqlite;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
 extern crate serde_json;
 extern crate slog;
 extern crate sloggers;
@@ -19,104 +28,560 @@ extern crate toml;
 extern crate xdg;
 extern crate xml;
 
+mod command;
+mod discover;
 mod errors;
 mod feeds;
+mod filter;
+mod hook;
 mod http;
 mod message;
 mod opml;
+mod outbox;
+mod readability;
+mod rss2email;
 mod settings;
+mod sqlite;
+mod throttle;
 
 use std::str::FromStr;
-use std::collections::HashSet;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::mem;
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use chrono::{Local, TimeZone};
 
 use lettre::EmailTransport;
 use lettre::file::FileEmailTransport;
 use lettre::sendmail::SendmailTransport;
 
-use settings::{MailBackend, Settings};
-use feeds::Feeds;
+use filter::{Filter, FilterAction, FilterField, Filters};
+use hook::Hook;
+use message::{Entry, Messages};
+use outbox::OutgoingEmail;
+use settings::{DigestMode, MailBackend, Settings};
+use throttle::Throttle;
+use feeds::{Feed, Feeds, Health};
 
 use sloggers::Build;
 use sloggers::terminal::{TerminalLoggerBuilder, Destination};
 use sloggers::types::Severity;
 
-fn add(feeds: &mut Feeds, name: &str, url: &str) {
-    if !feeds.contains(name) {
-        feeds.push(name, url);
+fn add(feeds: &mut Feeds, command: &clap::ArgMatches) {
+    let (name, url) = match command.value_of("url") {
+        Some(url) => (command.value_of("name"), url),
+        None => (None, command.value_of("name").unwrap()),
+    };
+    let digest = match command.value_of("digest") {
+        Some(digest) => match DigestMode::from_str(digest) {
+            Ok(digest) => Some(digest),
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        },
+        None => None,
+    };
+    let max_per_run = match command.value_of("max_per_run").map(|max| usize::from_str(max)) {
+        Some(Ok(max)) => Some(max),
+        Some(Err(e)) => {
+            eprintln!("invalid maximum number of entries per run: {}", e);
+            return;
+        }
+        None => None,
+    };
+    let interval = match command.value_of("interval").map(|interval| u64::from_str(interval)) {
+        Some(Ok(interval)) => Some(interval),
+        Some(Err(e)) => {
+            eprintln!("invalid interval: {}", e);
+            return;
+        }
+        None => None,
+    };
+    // the URL may be the one of a web page advertising the feed
+    let (url, title) = if !command.is_present("no_check") {
+        match discover::find(url) {
+            Ok(found) => found,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        }
+    } else {
+        (url.to_string(), String::new())
+    };
+    let name = match name {
+        Some(name) => name.to_string(),
+        None if !title.trim().is_empty() => title.trim().to_string(),
+        None => {
+            eprintln!("{} has no title, the feed needs a name", url);
+            return;
+        }
+    };
+    if feeds.contains(&name) {
+        eprintln!("{} already exists", name);
+        return;
+    }
+    let feed = feeds.push(&name, &url);
+    feed.options.fetch_full_content = command.is_present("full");
+    feed.options.digest = digest;
+    feed.options.hook = command.value_of("hook").map(|hook| hook.to_string());
+    feed.options.command = command.value_of("command").map(|command| command.to_string());
+    feed.options.max_per_run = max_per_run;
+    feed.options.interval = interval;
+    feed.options.to = command.value_of("to").map(|to| to.to_string());
+    feed.category = command.value_of("category").map(|category| category.to_string());
+    println!("added {} ({})", name, url);
+}
+
+/// Fetch a feed and tell which entries would be sent, without sending them.
+fn test(feeds: &Feeds, selector: &str) {
+    let feed = match feeds.select_one(selector) {
+        Ok(index) => &feeds.feeds[index],
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    println!("url: {}", feed.url);
+    let document = match http::fetch(&feed.url) {
+        Ok(document) => document,
+        Err(e) => {
+            println!("error: {}", e);
+            return;
+        }
+    };
+    println!("status: {}", document.status);
+    println!("content type: {}",
+             document.content_type.as_ref().map_or("unknown", |content_type| content_type.as_str()));
+    println!("encoding: {}",
+             document.encoding().unwrap_or_else(|| "unknown".to_string()));
+    let messages = match Messages::new(&document.text) {
+        Ok(messages) => messages,
+        Err(e) => {
+            println!("error: {}", e);
+            return;
+        }
+    };
+    println!("format: {}", messages.format);
+    println!("title: {}", messages.feed_name);
+    println!("entries: {}", messages.vec.len());
+    let filters = match Filters::new(&feed.filters) {
+        Ok(filters) => filters,
+        Err(e) => {
+            println!("error: {}", e);
+            return;
+        }
+    };
+    for entry in &messages.vec {
+        if feed.has_seen(entry) {
+            continue;
+        }
+        let state = if queued(feed, &feeds.outbox, entry) {
+            "queued"
+        } else {
+            if filters.accept(entry) { "new" } else { "filtered" }
+        };
+        println!("{}: {} ({})", state, entry.title, entry.url);
+    }
+}
+
+/// Show the email which would be sent for an entry of a feed.
+fn preview(settings: &Settings, feeds: &Feeds, selector: &str, entry: Option<&str>, raw: bool) {
+    let feed = match feeds.select_one(selector) {
+        Ok(index) => &feeds.feeds[index],
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let number = match entry.map(|entry| usize::from_str(entry)) {
+        Some(Ok(number)) => number,
+        Some(Err(e)) => {
+            eprintln!("invalid entry number: {}", e);
+            return;
+        }
+        None => 0,
+    };
+    let messages = match http::get(&feed.url).and_then(|data| Messages::new(&data)) {
+        Ok(messages) => messages,
+        Err(e) => {
+            eprintln!("{} {}: {}", feed.name, feed.url, e);
+            return;
+        }
+    };
+    let count = messages.vec.len();
+    let entry = match messages.vec.into_iter().nth(number) {
+        Some(entry) => entry,
+        None => {
+            eprintln!("{} has {} entries", feed.name, count);
+            return;
+        }
+    };
+
+    let mut hooks = HashMap::new();
+    let entry = match load_hook(settings, feed, &mut hooks)
+              .and_then(|hook| process_entry(settings, feed, hook, entry)) {
+        Ok(Some(entry)) => entry,
+        Ok(None) => {
+            println!("the entry is dropped by the hook or the command of {}", feed.name);
+            return;
+        }
+        Err(e) => {
+            eprintln!("{} {}", feed.name, e);
+            return;
+        }
+    };
+    let message = Messages::build_message(settings, &messages.feed_name, &entry);
+    let message = OutgoingEmail::new(&message, Vec::new()).message;
+
+    let pager = match env::var("PAGER") {
+        Ok(ref pager) if !raw && !pager.is_empty() => pager.clone(),
+        _ => {
+            print!("{}", message);
+            return;
+        }
+    };
+    let child = process::Command::new("sh")
+        .arg("-c")
+        .arg(&pager)
+        .stdin(process::Stdio::piped())
+        .spawn();
+    match child {
+        Ok(mut child) => {
+            if let Some(mut stdin) = child.stdin.take() {
+                // the pager may be quit before reading everything
+                let _ = stdin.write_all(message.as_bytes());
+            }
+            let _ = child.wait();
+        }
+        Err(e) => {
+            eprintln!("could not run {}: {}", pager, e);
+            print!("{}", message);
+        }
     }
 }
 
 fn list(feeds: &Feeds) {
     let mut index: u64 = 0;
     for ref feed in &feeds.feeds {
-        println!("{}: [{}] {} ({})",
+        println!("{}: [{}] {} ({}){}{}",
         index,
         if feed.paused { " " } else { "*" },
         feed.name,
         feed.url,
+        if feed.filtered != 0 { format!(" {} filtered", feed.filtered) } else { String::new() },
+        match feed.paused_reason {
+            Some(ref reason) => format!(" paused: {}", reason),
+            None => String::new(),
+        },
         );
         index += 1;
     }
 }
 
-fn pause(feeds: &mut Feeds, indexes: Option<clap::Values>) {
-    if let Some(indexes) = indexes {
-        for index in indexes {
-            let index = usize::from_str(index).unwrap();
-            feeds.feeds[index].paused = true;
-        }
-    } else {
-        for ref mut feed in &mut feeds.feeds {
-            feed.paused = true;
+fn date(timestamp: Option<i64>) -> String {
+    match timestamp {
+        Some(timestamp) => Local.timestamp(timestamp, 0).format("%Y-%m-%d %H:%M").to_string(),
+        None => "never".to_string(),
+    }
+}
+
+/// List the feeds from the least to the most healthy: failing for the
+/// longest time first, then the ones without a recent success.
+fn status(feeds: &Feeds) {
+    let mut indexes: Vec<usize> = (0..feeds.feeds.len()).collect();
+    indexes.sort_by_key(|&index| {
+                            let health = &feeds.feeds[index].health;
+                            (Reverse(health.failures), health.last_success.is_some(), health.last_success)
+                        });
+    for index in indexes {
+        let feed = &feeds.feeds[index];
+        let health = &feed.health;
+        println!("{}: [{}] {} {} failures, last success {}, status {}, {} delivered",
+                 index,
+                 if feed.paused { " " } else { "*" },
+                 feed.name,
+                 health.failures,
+                 date(health.last_success),
+                 health.last_status.map_or("-".to_string(), |status| status.to_string()),
+                 health.delivered);
+        if health.failures != 0 {
+            if let Some(ref error) = health.last_error {
+                println!("    {}: {}", date(health.last_fetch), error);
+            }
         }
     }
 }
 
-fn unpause(feeds: &mut Feeds, indexes: Option<clap::Values>) {
-    if let Some(indexes) = indexes {
-        for index in indexes {
-            let index = usize::from_str(index).unwrap();
-            feeds.feeds[index].paused = false;
+fn show(feeds: &Feeds, selector: &str) {
+    let feed = match feeds.select_one(selector) {
+        Ok(index) => &feeds.feeds[index],
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
         }
-    } else {
-        for ref mut feed in &mut feeds.feeds {
-            feed.paused = false;
+    };
+    println!("name: {}", feed.name);
+    println!("url: {}", feed.url);
+    match feed.paused_reason {
+        Some(ref reason) if feed.paused => println!("paused: {} ({})", feed.paused, reason),
+        _ => println!("paused: {}", feed.paused),
+    }
+    if let Some(ref category) = feed.category {
+        println!("category: {}", category);
+    }
+    println!("fetch full content: {}", feed.options.fetch_full_content);
+    if let Some(digest) = feed.options.digest {
+        println!("digest: {:?}", digest);
+    }
+    if let Some(ref hook) = feed.options.hook {
+        println!("hook: {}", hook);
+    }
+    if let Some(ref command) = feed.options.command {
+        println!("command: {}", command);
+    }
+    if let Some(max_per_run) = feed.options.max_per_run {
+        println!("max per run: {}", max_per_run);
+    }
+    if let Some(interval) = feed.options.interval {
+        println!("interval: {} minutes", interval);
+    }
+    if let Some(ref to) = feed.options.to {
+        println!("to: {}", to);
+    }
+    println!("seen entries: {}", feed.seen.len());
+    println!("pending entries: {}", feed.pending.len());
+    println!("filtered entries: {}", feed.filtered);
+    println!("delivered entries: {}", feed.health.delivered);
+    println!("last fetch: {}", date(feed.health.last_fetch));
+    println!("last success: {}", date(feed.health.last_success));
+    if feed.health.failures != 0 {
+        println!("failures: {}", feed.health.failures);
+        if let Some(ref error) = feed.health.last_error {
+            println!("last error: {}", error);
         }
     }
+    for (index, filter) in feed.filters.iter().enumerate() {
+        println!("filter {}: {:?} {:?} {}{}",
+                 index,
+                 filter.action,
+                 filter.field,
+                 filter.pattern,
+                 if filter.regex { " (regex)" } else { "" });
+    }
 }
 
-fn delete(feeds: &mut Feeds, indexes: Option<clap::Values>) {
+fn filter_add(feeds: &mut Feeds, command: &clap::ArgMatches) {
+    let index = match feeds.select_one(command.value_of("index").unwrap()) {
+        Ok(index) => index,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let filter = FilterAction::from_str(command.value_of("action").unwrap())
+        .and_then(|action| {
+            let field = FilterField::from_str(command.value_of("field").unwrap_or("any"))?;
+            Filter::new(action,
+                        field,
+                        command.value_of("pattern").unwrap(),
+                        command.is_present("regex"))
+        });
+    match filter {
+        Ok(filter) => feeds.feeds[index].filters.push(filter),
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+/// Indexes into a list of `len` items, sorted and without duplicates so
+/// they can be removed from the last one. Nothing is returned if one of
+/// them is invalid.
+fn parse_indexes(values: clap::Values, len: usize) -> errors::Result<Vec<usize>> {
     let mut idxs = Vec::new();
-    if let Some(indexes) = indexes {
+    for value in values {
+        match usize::from_str(value) {
+            Ok(idx) if idx < len => idxs.push(idx),
+            _ => bail!("invalid index {}, there are {} of them", value, len),
+        }
+    }
+    idxs.sort();
+    idxs.dedup();
+    Ok(idxs)
+}
+
+fn filter_delete(feeds: &mut Feeds, command: &clap::ArgMatches) {
+    let feed = match feeds.select_one(command.value_of("index").unwrap()) {
+        Ok(index) => &mut feeds.feeds[index],
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let idxs = match parse_indexes(command.values_of("filter").unwrap(), feed.filters.len()) {
+        Ok(idxs) => idxs,
+        Err(e) => {
+            eprintln!("{} {}", feed.name, e);
+            return;
+        }
+    };
+    for idx in idxs.iter().rev() {
+        feed.filters.remove(*idx);
+    }
+}
+
+/// Change the name, URL or settings of a feed, keeping its history.
+fn edit(feeds: &mut Feeds, command: &clap::ArgMatches) {
+    let index = match feeds.select_one(command.value_of("index").unwrap()) {
+        Ok(index) => index,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    // the changes are made on a copy, kept only if all of them are valid
+    let mut edited = feeds.feeds[index].clone();
+    if let Some(url) = command.value_of("url") {
+        // the state of the previous URL says nothing about the new one
+        if http::normalize(url) != http::normalize(&edited.url) {
+            edited.health = Health::default();
+            edited.interval = None;
+            edited.next_fetch = None;
+            edited.paused_reason = None;
+        }
+        edited.url = url.to_string();
+    }
+    for setting in command.values_of("set").into_iter().flat_map(|values| values) {
+        let result = match setting.find('=') {
+            Some(position) => edited.set(&setting[..position], &setting[position + 1..]),
+            None => Err(format!("invalid setting {}, expected key=value", setting).into()),
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            return;
+        }
+    }
+    if let Some(name) = command.value_of("name") {
+        if name != edited.name {
+            if let Err(e) = feeds.rename(index, name) {
+                eprintln!("{}", e);
+                return;
+            }
+            edited.name = name.to_string();
+        }
+    }
+    feeds.feeds[index] = edited;
+}
+
+/// Indexes of the feeds given on the command line, all of them when none is given.
+fn selected(feeds: &Feeds, selectors: Option<clap::Values>) -> Option<Vec<usize>> {
+    let indexes = match selectors {
+        Some(selectors) => feeds.select(&selectors.collect::<Vec<_>>()),
+        None => Ok((0..feeds.feeds.len()).collect()),
+    };
+    match indexes {
+        Ok(indexes) => Some(indexes),
+        Err(e) => {
+            eprintln!("{}", e);
+            None
+        }
+    }
+}
+
+fn pause(feeds: &mut Feeds, selectors: Option<clap::Values>) {
+    if let Some(indexes) = selected(feeds, selectors) {
         for index in indexes {
-            idxs.push(usize::from_str(index).unwrap());
+            feeds.feeds[index].paused = true;
         }
-        idxs.sort();
-        for idx in idxs.iter().rev() {
-            feeds.feeds.remove(idx.clone());
+    }
+}
+
+fn unpause(feeds: &mut Feeds, selectors: Option<clap::Values>) {
+    if let Some(indexes) = selected(feeds, selectors) {
+        for index in indexes {
+            feeds.feeds[index].paused = false;
+            feeds.feeds[index].paused_reason = None;
         }
     }
 }
 
-fn reset(feeds: &mut Feeds, indexes: Option<clap::Values>) {
-    if let Some(indexes) = indexes {
+fn delete(feeds: &mut Feeds, selectors: Option<clap::Values>) {
+    if let Some(indexes) = selected(feeds, selectors) {
+        for index in indexes.iter().rev() {
+            feeds.feeds.remove(*index);
+        }
+    }
+}
+
+fn reset(feeds: &mut Feeds, selectors: Option<clap::Values>) {
+    if let Some(indexes) = selected(feeds, selectors) {
         for index in indexes {
-            let index = usize::from_str(index).unwrap();
             feeds.feeds[index].seen.clear();
         }
+    }
+}
+
+fn opmlimport(feeds: &mut Feeds, path: Option<&str>, merge: bool, replace: bool, dry_run: bool) {
+    let mode = if merge {
+        opml::Mode::Merge
+    } else if replace {
+        opml::Mode::Replace
     } else {
-        for ref mut feed in &mut feeds.feeds {
-            feed.seen.clear();
-        }
+        opml::Mode::Add
+    };
+    if let Err(e) = opml::import(feeds, path.unwrap(), mode, dry_run) {
+        eprintln!("{}", e);
     }
 }
 
-fn opmlimport(mut feeds: &mut Feeds, path: Option<&str>) {
-    opml::import(&mut feeds, path.unwrap());
+fn opmlexport(feeds: &Feeds, path: Option<&str>) {
+    if let Err(e) = opml::export(feeds, path.unwrap()) {
+        eprintln!("{}", e);
+    }
 }
 
-fn opmlexport(mut feeds: &mut Feeds, path: Option<&str>) {
-    opml::export(&mut feeds, path.unwrap());
+fn import_rss2email_config(config: Option<&str>, rss2email_config: Option<&str>) {
+    let imported = match rss2email::config_file(rss2email_config)
+              .and_then(|path| rss2email::config(&path)) {
+        Ok(imported) => imported,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let config_file = match config {
+        Some(path) => path.into(),
+        None => Settings::default_path(),
+    };
+    if config_file.exists() {
+        println!("{} already exists, the imported configuration is:\n{}",
+                 config_file.display(),
+                 imported);
+        return;
+    }
+    if let Err(e) = fs::write(&config_file, imported) {
+        eprintln!("could not write {}: {}", config_file.display(), e);
+        process::exit(1);
+    }
+    println!("wrote {}", config_file.display());
+}
+
+fn import_rss2email(feeds: &mut Feeds, config: Option<&str>, data: Option<&str>) {
+    let imported = rss2email::config_file(config).and_then(|config| {
+        rss2email::data_file(data).and_then(|data| rss2email::import(&config, &data, feeds))
+    });
+    if let Err(e) = imported {
+        eprintln!("{}", e);
+    }
 }
 
 // awful hack
@@ -125,48 +590,538 @@ enum Lt {
     SendmailTransport(SendmailTransport)
 }
 
-fn run(settings: &Settings, feeds: &mut Feeds, no_send: bool) {
-    // awful hack since i can not get my trait object to work
-    let mut sender = match &settings.mail {
-        &MailBackend::File{ref path} => Lt::FileEmailTransport(FileEmailTransport::new(path)),
-        &MailBackend::SendMail{ref path} => match path {
-            &Some(ref path) => Lt::SendmailTransport(SendmailTransport::new_with_command(path.clone())),
-            &None => Lt::SendmailTransport(SendmailTransport::new())
+struct Sender {
+    transport: Lt,
+    throttle: Throttle,
+}
+
+impl Sender {
+    fn new(settings: &Settings) -> Self {
+        // awful hack since i can not get my trait object to work
+        let transport = match &settings.mail {
+            &MailBackend::File{ref path} => Lt::FileEmailTransport(FileEmailTransport::new(path)),
+            &MailBackend::SendMail{ref path} => match path {
+                &Some(ref path) => Lt::SendmailTransport(SendmailTransport::new_with_command(path.clone())),
+                &None => Lt::SendmailTransport(SendmailTransport::new())
+            }
+        };
+        let (limit, period) = settings.send_rate;
+        Sender {
+            transport: transport,
+            throttle: Throttle::new(limit, period),
+        }
+    }
+}
+
+fn send(sender: &mut Sender, email: &OutgoingEmail) -> Result<(), String> {
+    let email = email.sendable();
+    sender.throttle.wait();
+    // awful hack
+    match &mut sender.transport {
+        &mut Lt::FileEmailTransport(ref mut i) => match i.send(&email) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.to_string())
+        },
+        &mut Lt::SendmailTransport(ref mut i) => match i.send(&email) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.to_string())
+        }
+    }
+}
+
+/// Send `email`, or keep it in `outbox` to try again later.
+fn deliver(sender: &mut Sender, outbox: &mut Vec<OutgoingEmail>, mut email: OutgoingEmail) -> bool {
+    match send(sender, &email) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("{}: {}", email.subject(), e);
+            email.attempts += 1;
+            email.last_error = e;
+            outbox.push(email);
+            false
+        }
+    }
+}
+
+fn flush_outbox(feeds: &mut Feeds, sender: &mut Sender) {
+    let outbox = mem::replace(&mut feeds.outbox, Vec::new());
+    for email in outbox {
+        let entries = email.entries.clone();
+        if deliver(sender, &mut feeds.outbox, email) {
+            feeds.delivered(&entries);
+        }
+    }
+}
+
+/// Send one digest of `entries` per recipient, each entry is given with
+/// the name of its feed.
+fn send_digest(settings: &Settings,
+               sender: &mut Sender,
+               outbox: &mut Vec<OutgoingEmail>,
+               delivered: &mut Vec<(String, String)>,
+               name: &str,
+               entries: &[(&str, &(String, Entry))]) {
+    let mut recipients: Vec<&str> = entries
+        .iter()
+        .map(|&(_, &(_, ref entry))| entry.recipient(settings))
+        .collect();
+    recipients.sort();
+    recipients.dedup();
+
+    for to in recipients {
+        let entries: Vec<_> = entries
+            .iter()
+            .filter(|&&(_, &(_, ref entry))| entry.recipient(settings) == to)
+            .collect();
+        let digest: Vec<_> = entries.iter().map(|&&(_, pending)| pending.clone()).collect();
+        let ids: Vec<_> = entries
+            .iter()
+            .map(|&&(feed, &(_, ref entry))| (feed.to_string(), entry.id.clone()))
+            .collect();
+        let message = Messages::build_digest(&settings, to, name, &digest);
+        if deliver(sender, outbox, OutgoingEmail::new(&message, ids.clone())) {
+            delivered.extend(ids);
+        }
+    }
+}
+
+fn send_digests(settings: &Settings, feeds: &mut Feeds, sender: &mut Sender) {
+    let mut outbox = Vec::new();
+    let mut delivered = Vec::new();
+    {
+        let mut digest = Vec::new();
+        for feed in &feeds.feeds {
+            if feed.pending.is_empty() {
+                continue;
+            }
+            let entries = feed.pending.iter().map(|pending| (feed.name.as_str(), pending));
+            match feed.digest(settings) {
+                DigestMode::All => digest.extend(entries),
+                _ => {
+                    let entries: Vec<_> = entries.collect();
+                    send_digest(settings,
+                                sender,
+                                &mut outbox,
+                                &mut delivered,
+                                &feed.pending[0].0,
+                                &entries);
+                }
+            }
+        }
+        if !digest.is_empty() {
+            send_digest(settings,
+                        sender,
+                        &mut outbox,
+                        &mut delivered,
+                        "rust2email",
+                        &digest);
+        }
+    }
+    // entries which could not be sent wait in the outbox
+    for feed in feeds.feeds.iter_mut() {
+        feed.pending.clear();
+    }
+    feeds.outbox.extend(outbox);
+    feeds.delivered(&delivered);
+}
+
+fn outbox_list(feeds: &Feeds) {
+    for (index, email) in feeds.outbox.iter().enumerate() {
+        println!("{}: {} -> {} ({} attempts: {})",
+                 index,
+                 email.subject(),
+                 email.to.join(", "),
+                 email.attempts,
+                 email.last_error);
+    }
+}
+
+fn outbox_flush(settings: &Settings, feeds: &mut Feeds) {
+    let mut sender = Sender::new(settings);
+    flush_outbox(feeds, &mut sender);
+}
+
+fn outbox_delete(feeds: &mut Feeds, indexes: Option<clap::Values>) {
+    if let Some(indexes) = indexes {
+        let idxs = match parse_indexes(indexes, feeds.outbox.len()) {
+            Ok(idxs) => idxs,
+            Err(e) => {
+                eprintln!("outbox {}", e);
+                return;
+            }
+        };
+        for idx in idxs.iter().rev() {
+            // the entries are given up on, do not send them again
+            let email = feeds.outbox.remove(*idx);
+            feeds.mark_seen(&email.entries);
         }
+    }
+}
+
+/// Whether `entry` waits for a digest or in the outbox.
+fn queued(feed: &Feed, outbox: &[OutgoingEmail], entry: &Entry) -> bool {
+    feed.is_pending(entry) ||
+    outbox.iter().any(|email| {
+                          email.contains(&feed.name, &entry.id) ||
+                          feed.link_ids && email.contains(&feed.name, &entry.url)
+                      })
+}
+
+/// Tell the administrator when `feed` reaches `alert_failures` failures in
+/// a row, and when it works again, `failures` being the count before
+/// the last fetch.
+fn alert(settings: &Settings,
+         feed: &Feed,
+         failures: u64,
+         sender: &mut Sender,
+         outbox: &mut Vec<OutgoingEmail>) {
+    let threshold = settings.alert_failures;
+    let health = &feed.health;
+    let subject = if threshold == 0 {
+        return;
+    } else if failures < threshold && health.failures == threshold {
+        format!("rust2email: {} is failing", feed.name)
+    } else if failures >= threshold && health.failures == 0 {
+        format!("rust2email: {} works again", feed.name)
+    } else {
+        return;
     };
-    for ref mut feed in &mut feeds.feeds {
-        if !feed.paused {
-            match http::get_feed(&feed.url) {
-                Err(err) => {
-                    println!("{} {}", feed.name, err);
+
+    let mut body = format!("{} ({})\n\n", feed.name, feed.url);
+    if health.failures == 0 {
+        body.push_str(&format!("fetched after {} failures in a row\n", failures));
+    } else {
+        body.push_str(&format!("{} failures in a row, last success {}, last status {}\n\n",
+                               health.failures,
+                               date(health.last_success),
+                               health.last_status.map_or("-".to_string(), |status| status.to_string())));
+        for &(time, ref error) in &health.errors {
+            body.push_str(&format!("{}: {}\n", date(Some(time)), error));
+        }
+    }
+    let message = Messages::build_alert(settings, &subject, &body);
+    deliver(sender, outbox, OutgoingEmail::new(&message, Vec::new()));
+}
+
+/// Hook script of `feed`, loaded once and kept in `hooks`.
+fn load_hook<'a>(settings: &Settings,
+                 feed: &Feed,
+                 hooks: &'a mut HashMap<String, Hook>)
+                 -> errors::Result<Option<&'a Hook>> {
+    match feed.options.hook.as_ref().or(settings.hook.as_ref()) {
+        Some(path) => {
+            if !hooks.contains_key(path) {
+                hooks.insert(path.clone(), Hook::new(path)?);
+            }
+            Ok(hooks.get(path))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Prepare a new entry of `feed` for sending: recipient and headers,
+/// full content, hook and external command.
+///
+/// Returns `None` when the entry is dropped by the hook or the command.
+fn process_entry(settings: &Settings,
+                 feed: &Feed,
+                 hook: Option<&Hook>,
+                 mut entry: Entry)
+                 -> errors::Result<Option<Entry>> {
+    if entry.to.is_none() {
+        entry.to = feed.to(settings).cloned();
+    }
+    entry.headers.extend(feed.headers(settings).iter().cloned());
+    if feed.options.fetch_full_content {
+        match readability::fetch(&entry.url) {
+            Ok(article) => entry.body = article,
+            Err(e) => eprintln!("{} {}: {}", feed.name, entry.url, e),
+        }
+    }
+    if let Some(hook) = hook {
+        entry = match hook.apply(&feed.name, entry)? {
+            Some(processed) => processed,
+            None => return Ok(None),
+        };
+    }
+    if let Some(ref cmd) = feed.options.command {
+        let timeout = Duration::from_secs(settings.command_timeout);
+        entry = match command::apply(cmd, timeout, &feed.name, entry)? {
+            Some(processed) => processed,
+            None => return Ok(None),
+        };
+    }
+    Ok(Some(entry))
+}
+
+/// Fetch `feed` and send, or queue for the digests, its new entries.
+///
+/// Returns the polling interval advertised by the feed, if any.
+fn fetch_feed(settings: &Settings,
+              feed: &mut Feed,
+              outbox: &mut Vec<OutgoingEmail>,
+              sender: &mut Sender,
+              hooks: &mut HashMap<String, Hook>,
+              no_send: bool)
+              -> Option<u64> {
+    let hook = match load_hook(settings, feed, hooks) {
+        Ok(hook) => hook,
+        Err(e) => {
+            // better not to send anything than to send unprocessed entries
+            eprintln!("{} {}", feed.name, e);
+            return None;
+        }
+    };
+    let filters = match Filters::new(&feed.filters) {
+        Ok(filters) => filters,
+        Err(e) => {
+            eprintln!("{} {}", feed.name, e);
+            return None;
+        }
+    };
+    let failures = feed.health.failures;
+    let interval = match http::fetch(&feed.url) {
+        Err(err) => {
+            feed.health.failure(http::status(&err), err.to_string());
+            if let errors::ErrorKind::Gone(_) = *err.kind() {
+                println!("{} is gone, pausing it", feed.name);
+                feed.paused = true;
+                feed.paused_reason = Some("gone (410)".to_string());
+            } else {
+                println!("{} {}", feed.name, err);
+            }
+            None
+        }
+        Ok(document) => {
+            if let Some(url) = document.moved_to {
+                if settings.update_moved_feeds {
+                    println!("{} moved from {} to {}", feed.name, feed.url, url);
+                    feed.url = url;
+                } else {
+                    println!("{} moved to {}", feed.name, url);
+                }
+            }
+            match Messages::new(&document.text) {
+                Err(msg) => {
+                    println!("{} {}: {}", feed.name, feed.url, msg);
+                    feed.health.failure(Some(document.status.as_u16()), msg.to_string());
+                    None
                 }
-                Ok(data) => {
-                    match message::Messages::new(&settings, &data) {
-                        Err(msg) => {
-                            println!("{} {}: {}", feed.name, feed.url, msg);
+                Ok(messages) => {
+                    feed.health.success(document.status.as_u16());
+                    let mode = feed.digest(settings);
+                    let max_per_run = feed.options.max_per_run.unwrap_or(settings.max_per_feed);
+                    let mut sent = 0;
+                    let mut seen = HashSet::new();
+                    for mut entry in messages.vec {
+                        // queued entries are marked seen once they are sent
+                        if queued(feed, outbox, &entry) {
+                            continue;
                         }
-                        Ok(messages) => {
-                            let mut seen = HashSet::new();
-                            for (id, message) in messages.vec {
-                                seen.insert(id.clone());
-                                if !no_send && !feed.seen.contains(&id) {
-                                    // awful hack
-                                    match &mut sender {
-                                        &mut Lt::FileEmailTransport(ref mut i) => match i.send(&message) {
-                                            Ok(_) => (),
-                                            Err(e) => eprintln!("{}", e)
-                                        },
-                                        &mut Lt::SendmailTransport(ref mut i) => match i.send(&message) {
-                                            Ok(_) => (),
-                                            Err(e) => eprintln!("{}", e)
-                                        }
-                                    }
+                        if !no_send && !feed.has_seen(&entry) {
+                            if !filters.accept(&entry) {
+                                feed.filtered += 1;
+                                seen.insert(entry.id);
+                                continue;
+                            }
+                            if max_per_run != 0 && sent >= max_per_run {
+                                // keep the remaining entries for the next run
+                                continue;
+                            }
+                            let id = entry.id.clone();
+                            match process_entry(settings, feed, hook, entry) {
+                                Ok(Some(processed)) => entry = processed,
+                                Ok(None) => {
+                                    feed.filtered += 1;
+                                    seen.insert(id);
+                                    continue;
+                                }
+                                Err(e) => {
+                                    // try again at the next run
+                                    eprintln!("{} {}: {}", feed.name, id, e);
+                                    continue;
                                 }
                             }
-                            feed.seen = seen;
+                            sent += 1;
+                            if mode != DigestMode::None {
+                                feed.pending.push((messages.feed_name.clone(), entry));
+                                continue;
+                            }
+                            let message = Messages::build_message(&settings,
+                                                                  &messages.feed_name,
+                                                                  &entry);
+                            let email = OutgoingEmail::new(&message,
+                                                           vec![(feed.name.clone(), entry.id.clone())]);
+                            if !deliver(sender, outbox, email) {
+                                continue;
+                            }
+                            feed.health.delivered += 1;
                         }
+                        seen.insert(entry.id);
+                    }
+                    feed.seen = seen;
+                    // the seen entries now have their new id, the queued ones
+                    // keep the old one until they are sent
+                    if feed.link_ids && feed.pending.is_empty() &&
+                       !outbox.iter().any(|email| email.entries.iter().any(|&(ref name, _)| name == &feed.name)) {
+                        feed.link_ids = false;
                     }
+                    messages.interval
+                }
+            }
+        }
+    };
+    if !no_send {
+        alert(settings, feed, failures, sender, outbox);
+    }
+    interval
+}
+
+/// Send the digests if they are due.
+fn digests(settings: &Settings, feeds: &mut Feeds, sender: &mut Sender) {
+    let now = Local::now();
+    let due = match settings.digest_schedule.last_due(now) {
+        Some(due) => feeds.last_digest.map_or(true, |last| last < due.timestamp()),
+        None => true,
+    };
+    if due {
+        send_digests(settings, feeds, sender);
+        feeds.last_digest = Some(now.timestamp());
+    }
+}
+
+fn run(settings: &Settings, feeds: &mut Feeds, no_send: bool) {
+    let mut sender = Sender::new(settings);
+    if !no_send {
+        flush_outbox(feeds, &mut sender);
+    }
+    let mut hooks = HashMap::new();
+    for feed in feeds.feeds.iter_mut() {
+        if !feed.paused {
+            fetch_feed(settings, feed, &mut feeds.outbox, &mut sender, &mut hooks, no_send);
+        }
+    }
+
+    if !no_send {
+        digests(settings, feeds, &mut sender);
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Seconds between two attempts to lock the database again in daemon mode.
+const LOCK_RETRY: u64 = 10;
+
+/// Shortest interval in seconds between two fetches of a feed in daemon mode.
+const MIN_INTERVAL: u64 = 60;
+
+fn daemon(mut settings: Settings, feeds: &mut Feeds, config: Option<&str>) {
+    let running = Arc::new(AtomicBool::new(true));
+    {
+        let running = running.clone();
+        ctrlc::set_handler(move || running.store(false, Ordering::SeqCst))
+            .expect("could not install the signal handler");
+    }
+
+    // the files are watched to pick up the changes made by the other
+    // commands or to the configuration
+    let config_file = Settings::path(config).unwrap();
+    let data_file = feeds.path().to_path_buf();
+    let mut config_modified = modified(&config_file);
+    let save = |feeds: &mut Feeds| if let Err(e) = feeds.save() {
+        eprintln!("could not save the database: {}", e);
+    };
+
+    let mut sender = Sender::new(&settings);
+    let mut hooks = HashMap::new();
+    while running.load(Ordering::SeqCst) {
+        flush_outbox(feeds, &mut sender);
+
+        let now = Local::now().timestamp();
+        for index in 0..feeds.feeds.len() {
+            if !running.load(Ordering::SeqCst) {
+                break;
+            }
+            {
+                let feed = &mut feeds.feeds[index];
+                if feed.paused || feed.next_fetch.map_or(false, |next| next > now) {
+                    continue;
+                }
+                if let Some(interval) = fetch_feed(&settings,
+                                                   feed,
+                                                   &mut feeds.outbox,
+                                                   &mut sender,
+                                                   &mut hooks,
+                                                   false) {
+                    feed.interval = Some(interval);
                 }
+                let interval = feed.options
+                    .interval
+                    .map(|minutes| minutes * 60)
+                    .or(feed.interval)
+                    .unwrap_or(settings.interval * 60)
+                    .max(MIN_INTERVAL);
+                feed.next_fetch = Some(now + interval as i64);
+            }
+            save(feeds);
+        }
+        digests(&settings, feeds, &mut sender);
+        save(feeds);
+        let data_modified = modified(&data_file);
+
+        // sleep until the next feed is due, waking up to notice signals,
+        // modified files and scheduled digests; the other commands can use
+        // the database meanwhile
+        feeds.unlock();
+        let next = feeds
+            .feeds
+            .iter()
+            .filter(|feed| !feed.paused)
+            .filter_map(|feed| feed.next_fetch)
+            .min()
+            .unwrap_or(now + settings.interval as i64 * 60);
+        while running.load(Ordering::SeqCst) && Local::now().timestamp() < next {
+            thread::sleep(Duration::from_secs(1));
+            if modified(&config_file) != config_modified || modified(&data_file) != data_modified {
+                break;
+            }
+            if settings.digest_schedule.last_due(Local::now()).map_or(false, |due| {
+                feeds.last_digest.map_or(true, |last| last < due.timestamp())
+            }) {
+                break;
+            }
+        }
+
+        // the database has to be locked again before it is saved, even
+        // when stopping
+        while let Err(e) = feeds.lock() {
+            eprintln!("{}", e);
+            if !running.load(Ordering::SeqCst) {
+                // everything was saved before unlocking, and main must not
+                // save without the lock
+                process::exit(1);
+            }
+            // the lock file may be unusable for a while, e.g. on a full disk
+            thread::sleep(Duration::from_secs(LOCK_RETRY));
+        }
+        if modified(&data_file) != data_modified {
+            match feeds.reload() {
+                Ok(()) => println!("reloading {}", data_file.display()),
+                Err(e) => eprintln!("could not reload the database: {}", e),
+            }
+        }
+        if modified(&config_file) != config_modified {
+            config_modified = modified(&config_file);
+            match Settings::new(config) {
+                Ok(new) => {
+                    println!("reloading {}", config_file.display());
+                    settings = new;
+                    sender = Sender::new(&settings);
+                    hooks.clear();
+                }
+                Err(e) => eprintln!("could not reload the configuration: {}", e),
             }
         }
     }
@@ -184,37 +1139,113 @@ fn main() {
                              (about: "Fetch feeds and send entry emails")
                              (@arg nosend: -n --nosend "fetch feeds, but don't send email")
                             )
+                            (@subcommand daemon =>
+                             (about: "Keep running, fetching each feed at its own interval")
+                            )
                             (@subcommand add =>
                              (about: "Add a new feed to the database")
-                             (@arg name: +required "name of the new feed")
-                             (@arg url: +required "location of the new feed")
+                             (@arg name: +required "name of the new feed, or its location to use the title of the feed")
+                             (@arg url: "location of the new feed, or of a web page advertising it")
+                             (@arg full: -f --full "download the full article of each entry")
+                             (@arg digest: --digest +takes_value "digest mode for this feed (none, feed or all)")
+                             (@arg hook: --hook +takes_value "script run on each new entry of this feed")
+                             (@arg command: --command +takes_value "external command each new entry of this feed is piped through")
+                             (@arg max_per_run: --("max-per-run") +takes_value "maximum number of entries sent per run for this feed")
+                             (@arg interval: --interval +takes_value "minutes between two fetches of this feed in daemon mode")
+                             (@arg to: --to +takes_value "recipient of the entries of this feed")
+                             (@arg category: --category +takes_value "category of this feed, e.g. news/tech")
+                             (@arg no_check: --("no-check") "add the feed without downloading it")
+                            )
+                            (@subcommand test =>
+                             (about: "Fetch a feed and show its new entries, without sending them")
+                             (@arg index: +required "feed index, name or part of its URL")
+                            )
+                            (@subcommand preview =>
+                             (about: "Show the email of an entry of a feed, without sending it")
+                             (@arg index: +required "feed index, name or part of its URL")
+                             (@arg entry: -e --entry +takes_value "number of the entry in the feed, 0 by default")
+                             (@arg raw: -r --raw "print the message instead of opening it in $PAGER")
                             )
                             (@subcommand list =>
                              (about: "List all the feeds in the database")
                             )
+                            (@subcommand status =>
+                             (about: "List the feeds from the least to the most healthy")
+                            )
+                            (@subcommand show =>
+                             (about: "Show the details of a feed")
+                             (@arg index: +required "feed index, name or part of its URL")
+                            )
+                            (@subcommand filter =>
+                             (about: "Manage the entry filters of a feed")
+                             (@setting SubcommandRequiredElseHelp)
+                             (@subcommand add =>
+                              (about: "Add a filter to a feed")
+                              (@arg index: +required "feed index, name or part of its URL")
+                              (@arg action: +required "include or exclude the matching entries")
+                              (@arg pattern: +required "keyword or regular expression to match")
+                              (@arg field: -f --field +takes_value "entry field to match (any, title, body, author, categories or url)")
+                              (@arg regex: -r --regex "the pattern is a regular expression")
+                             )
+                             (@subcommand delete =>
+                              (about: "Remove filters from a feed")
+                              (@arg index: +required "feed index, name or part of its URL")
+                              (@arg filter: +multiple +required "filter indexes, as given by show")
+                             )
+                            )
+                            (@subcommand outbox =>
+                             (about: "Manage the emails which could not be sent")
+                             (@setting SubcommandRequiredElseHelp)
+                             (@subcommand list =>
+                              (about: "List the emails waiting in the outbox")
+                             )
+                             (@subcommand flush =>
+                              (about: "Try to send the emails of the outbox now")
+                             )
+                             (@subcommand delete =>
+                              (about: "Remove emails from the outbox, their entries are not sent")
+                              (@arg index: +multiple +required "outbox indexes")
+                             )
+                            )
+                            (@subcommand edit =>
+                             (about: "Change the name, URL or settings of a feed, keeping its history")
+                             (@arg index: +required "feed index, name or part of its URL")
+                             (@arg name: --name +takes_value "new name")
+                             (@arg url: --url +takes_value "new location")
+                             (@arg set: --set +takes_value +multiple number_of_values(1) "key=value, for paused, full, digest, hook, command, max-per-run, interval, to or category (empty to unset)")
+                            )
                             (@subcommand pause =>
                              (about: "Pause a feed (disable fetching)")
-                             (@arg index: +multiple "feed indexes")
+                             (@arg index: +multiple "feeds: indexes, ranges such as 3-7, names, globs or parts of their URL")
                             )
                             (@subcommand unpause =>
                              (about: "Unpause a feed (enable fetching)")
-                             (@arg index: +multiple "feed indexes")
+                             (@arg index: +multiple "feeds: indexes, ranges such as 3-7, names, globs or parts of their URL")
                             )
                             (@subcommand delete =>
                              (about: "Remove a feed from the database")
-                             (@arg index: +multiple +required "feed indexes")
+                             (@arg index: +multiple +required "feeds: indexes, ranges such as 3-7, names, globs or parts of their URL")
                             )
                             (@subcommand reset =>
                              (about: "Forget dynamic feed data (e.g. to re-send old entries)")
-                             (@arg index: +multiple "feed indexes")
+                             (@arg index: +multiple "feeds: indexes, ranges such as 3-7, names, globs or parts of their URL")
                             )
                             (@subcommand opmlimport =>
                              (about: "Import configuration from OPML.")
                              (@arg path: +required "path for imported OPML")
+                             (@arg dry_run: -n --("dry-run") "only report what would be imported")
+                             (@arg merge: --merge conflicts_with[replace] "update the category and options of the feeds with the same URL")
+                             (@arg replace: --replace "replace the feeds with the same URL or name")
                             )
                             (@subcommand opmlexport =>
                              (about: "Export configuration from OPML.")
-                             (@arg path: +required "path for exported OPML")
+                             (@arg path: +required "path for exported OPML, - for the standard output")
+                            )
+                            (@subcommand import_rss2email =>
+                             (name: "import-rss2email")
+                             (about: "Import the feeds and configuration of rss2email")
+                             (@arg rss2email_config: --("rss2email-config") +takes_value "path to rss2email.cfg")
+                             (@arg rss2email_data: --("rss2email-data") +takes_value "path to rss2email.json")
                             )
                            )
             .get_matches();
@@ -224,26 +1255,73 @@ fn main() {
     builder.destination(Destination::Stderr);
     let logger = builder.build().unwrap();
 
+    if let ("import-rss2email", Some(command)) = matches.subcommand() {
+        // the configuration has to exist before the feeds can be imported
+        import_rss2email_config(matches.value_of("config"), command.value_of("rss2email_config"));
+    }
+
     let settings = Settings::new(matches.value_of("config")).unwrap();
 
-    let mut feeds = Feeds::new(matches.value_of("data")).unwrap();
+    let mut feeds = match Feeds::new(matches.value_of("data"), settings.storage) {
+        Ok(feeds) => feeds,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
 
     match matches.subcommand() {
         ("run", Some(command)) => run(&settings, &mut feeds, command.is_present("nosend")),
-        ("add", Some(command)) => {
-            add(&mut feeds,
-                command.value_of("name").unwrap(),
-                command.value_of("url").unwrap())
+        ("daemon", Some(_)) => {
+            daemon(settings, &mut feeds, matches.value_of("config"))
+        }
+        ("add", Some(command)) => add(&mut feeds, command),
+        ("test", Some(command)) => test(&feeds, command.value_of("index").unwrap()),
+        ("preview", Some(command)) => {
+            preview(&settings,
+                    &feeds,
+                    command.value_of("index").unwrap(),
+                    command.value_of("entry"),
+                    command.is_present("raw"))
         }
         ("list", Some(_)) => list(&mut feeds),
+        ("status", Some(_)) => status(&feeds),
+        ("show", Some(command)) => show(&feeds, command.value_of("index").unwrap()),
+        ("filter", Some(command)) => {
+            match command.subcommand() {
+                ("add", Some(command)) => filter_add(&mut feeds, command),
+                ("delete", Some(command)) => filter_delete(&mut feeds, command),
+                _ => {}
+            }
+        }
+        ("outbox", Some(command)) => {
+            match command.subcommand() {
+                ("list", Some(_)) => outbox_list(&feeds),
+                ("flush", Some(_)) => outbox_flush(&settings, &mut feeds),
+                ("delete", Some(command)) => outbox_delete(&mut feeds, command.values_of("index")),
+                _ => {}
+            }
+        }
+        ("edit", Some(command)) => edit(&mut feeds, command),
         ("pause", Some(command)) => pause(&mut feeds, command.values_of("index")),
         ("unpause", Some(command)) => unpause(&mut feeds, command.values_of("index")),
         ("delete", Some(command)) => delete(&mut feeds, command.values_of("index")),
         ("reset", Some(command)) => reset(&mut feeds, command.values_of("index")),
-        ("opmlimport", Some(command)) => opmlimport(&mut feeds, command.value_of("path")),
-        ("opmlexport", Some(command)) => opmlexport(&mut feeds, command.value_of("path")),
+        ("opmlimport", Some(command)) => {
+            opmlimport(&mut feeds,
+                       command.value_of("path"),
+                       command.is_present("merge"),
+                       command.is_present("replace"),
+                       command.is_present("dry_run"))
+        }
+        ("opmlexport", Some(command)) => opmlexport(&feeds, command.value_of("path")),
+        ("import-rss2email", Some(command)) => {
+            import_rss2email(&mut feeds,
+                             command.value_of("rss2email_config"),
+                             command.value_of("rss2email_data"))
+        }
         _ => {}
     }
 
-    feeds.save(matches.value_of("data")).unwrap();
+    feeds.save().unwrap();
 }
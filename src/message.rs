@@ -6,60 +6,195 @@ use settings::Settings;
 use html2text;
 use errors::*;
 
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Entry {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    pub body: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// recipient overriding the configuration file, set by hooks
+    #[serde(default)]
+    pub to: Option<String>,
+    /// additional email headers, set by hooks
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+}
+
+impl Entry {
+    pub fn recipient<'a>(&'a self, settings: &'a Settings) -> &'a str {
+        match self.to {
+            Some(ref to) => to,
+            None => &settings.to,
+        }
+    }
+}
+
 pub struct Messages {
-    pub vec: Vec<(String, Email)>,
+    /// RSS or Atom
+    pub format: &'static str,
+    pub feed_name: String,
+    pub vec: Vec<Entry>,
+    /// polling interval advertised by the feed, in seconds
+    pub interval: Option<u64>,
+}
+
+/// Interval from the syndication module (`sy:updatePeriod` and `sy:updateFrequency`).
+fn update_interval(period: Option<&str>, frequency: Option<&str>) -> Option<u64> {
+    let period = match period.map(|period| period.trim()) {
+        Some("hourly") => 3600,
+        Some("daily") => 86400,
+        Some("weekly") => 604800,
+        Some("monthly") => 2592000,
+        Some("yearly") => 31536000,
+        _ => return None,
+    };
+    let frequency = frequency
+        .and_then(|frequency| frequency.trim().parse().ok())
+        .unwrap_or(1);
+    if frequency == 0 {
+        return None;
+    }
+    Some(period / frequency)
+}
+
+fn fill_template(template: &str, feed_name: &str, entry: &Entry) -> String {
+    template
+        .replace(r"<feed_name>", feed_name)
+        .replace(r"<entry_url>", &entry.url)
+        .replace(r"<entry_name>", &entry.title)
+        .replace(r"<entry_body>", &entry.body)
+}
+
+fn build_email(settings: &Settings,
+               to: &str,
+               headers: &[(String, String)],
+               from: &str,
+               subject: &str,
+               body: &str)
+               -> Email {
+    let mut body = body.to_string();
+    if settings.text {
+        body = html2text::from_read(body.as_bytes(), settings.text_wrap);
+    }
+    let mut email = EmailBuilder::new()
+        .to(to)
+        .from((settings.from_address.as_str(), from))
+        .subject(subject);
+
+    for &(ref name, ref value) in headers {
+        email = email.header((name.as_str(), value.as_str()));
+    }
+
+    email = match settings.text {
+        true => email.text(body.as_str()),
+        false => email.html(body.as_str()),
+    };
+
+    email.build().unwrap()
+}
+
+/// Id of an entry without guid nor link, from its title and body.
+///
+/// FNV-1a is used because, unlike the hasher of the standard library, its
+/// result never changes between builds and the ids are kept in the database.
+fn content_id(title: &str, body: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &byte in title.as_bytes().iter().chain(&[0]).chain(body.as_bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
 }
 
 impl Messages {
-    fn build_message(settings: &Settings,
-                     feed_name: &str,
-                     entry_name: &str,
-                     entry_url: &str,
-                     entry_text: &str)
-                     -> Email {
-
-        let subject = settings
-            .subject
-            .clone()
-            .replace(r"<feed_name>", feed_name)
-            .replace(r"<entry_url>", entry_url)
-            .replace(r"<entry_name>", entry_name)
-            .replace(r"<entry_body>", entry_text);
-        let from = settings
-            .from_display_name
-            .clone()
-            .replace(r"<feed_name>", feed_name)
-            .replace(r"<entry_url>", entry_url)
-            .replace(r"<entry_name>", entry_name)
-            .replace(r"<entry_body>", entry_text);
-        let mut body = settings
-            .body
-            .clone()
-            .replace(r"<feed_name>", feed_name)
-            .replace(r"<entry_url>", entry_url)
-            .replace(r"<entry_name>", entry_name)
-            .replace(r"<entry_body>", entry_text);
-
-        if settings.text {
-            body = html2text::from_read(body.as_bytes(), settings.text_wrap);
+    pub fn build_message(settings: &Settings, feed_name: &str, entry: &Entry) -> Email {
+        let subject = fill_template(&settings.subject, feed_name, entry);
+        let from = fill_template(&settings.from_display_name, feed_name, entry);
+        let body = fill_template(&settings.body, feed_name, entry);
+
+        build_email(settings,
+                    entry.recipient(settings),
+                    &entry.headers,
+                    &from,
+                    &subject,
+                    &body)
+    }
+
+    /// Build a single email from several entries, each entry is given
+    /// with the name of the feed it comes from.
+    pub fn build_digest(settings: &Settings,
+                        to: &str,
+                        digest_name: &str,
+                        entries: &[(String, Entry)])
+                        -> Email {
+        let mut toc = String::new();
+        let mut body = String::new();
+        for (index, &(ref feed_name, ref entry)) in entries.iter().enumerate() {
+            let index = index.to_string();
+            toc.push_str(&settings
+                              .digest_toc_entry
+                              .replace(r"<entry_index>", &index)
+                              .replace(r"<feed_name>", feed_name)
+                              .replace(r"<entry_url>", &entry.url)
+                              .replace(r"<entry_name>", &entry.title));
+            body.push_str(&fill_template(&settings.digest_entry, feed_name, entry)
+                               .replace(r"<entry_index>", &index));
         }
-        let mut email = EmailBuilder::new()
-            .to(settings.to.as_str())
-            .from((settings.from_address.as_str(), from.as_str()))
-            .subject(subject.as_str());
-
-        email = match settings.text {
-            true => email.text(body.as_str()),
-            false => email.html(body.as_str()),
+
+        let count = entries.len().to_string();
+        let digest = |template: &str| {
+            template
+                .replace(r"<feed_name>", digest_name)
+                .replace(r"<digest_count>", &count)
+                .replace(r"<digest_toc>", &toc)
+                .replace(r"<digest_entries>", &body)
         };
 
-        email.build().unwrap()
+        build_email(settings,
+                    to,
+                    &[],
+                    &digest(&settings.from_display_name),
+                    &digest(&settings.digest_subject),
+                    &digest(&settings.digest_body))
+    }
+
+    /// Build an email about rust2email itself for `alert_to`.
+    pub fn build_alert(settings: &Settings, subject: &str, body: &str) -> Email {
+        EmailBuilder::new()
+            .to(settings.alert_to.as_str())
+            .from((settings.from_address.as_str(), "rust2email"))
+            .subject(subject)
+            .text(body)
+            .build()
+            .unwrap()
     }
 
-    fn from_rss(settings: &Settings, channel: &rss::Channel) -> Self {
-        let mut messages = Messages { vec: Vec::new() };
+    fn from_rss(channel: &rss::Channel) -> Self {
+        let sy = |name| {
+            channel
+                .extensions()
+                .get("sy")
+                .and_then(|sy| sy.get(name))
+                .and_then(|values| values.first())
+                .and_then(|value| value.value())
+        };
+        let ttl = channel
+            .ttl()
+            .and_then(|ttl| ttl.trim().parse::<u64>().ok())
+            .map(|minutes| minutes * 60);
+        let mut messages = Messages {
+            format: "RSS",
+            feed_name: channel.title().to_string(),
+            vec: Vec::new(),
+            interval: ttl.or(update_interval(sy("updatePeriod"), sy("updateFrequency"))),
+        };
         for item in channel.items().iter() {
-            let link = item.link().clone().unwrap();
+            let link = item.link().unwrap_or("");
+            let title = item.title().unwrap_or("no_title");
             let text = if let Some(text) = item.content().clone() {
                 text
             } else {
@@ -69,20 +204,45 @@ impl Messages {
                     ""
                 }
             };
+            // entries are identified by their guid, or else by their link,
+            // or else by their content
+            let id = match item.guid().map(|guid| guid.value().trim()) {
+                Some(guid) if !guid.is_empty() => guid.to_string(),
+                _ if !link.trim().is_empty() => link.to_string(),
+                _ => content_id(title, text),
+            };
 
-            let email = Messages::build_message(&settings,
-                                                channel.title(),
-                                                item.title().unwrap_or("no_title"),
-                                                link,
-                                                text);
-
-            messages.vec.push((link.to_string(), email));
+            messages.vec.push(Entry {
+                                  id: id,
+                                  title: title.to_string(),
+                                  url: link.to_string(),
+                                  body: text.to_string(),
+                                  author: item.author().unwrap_or("").to_string(),
+                                  categories: item.categories()
+                                      .iter()
+                                      .map(|category| category.name().to_string())
+                                      .collect(),
+                                  to: None,
+                                  headers: Vec::new(),
+                              });
         }
         messages
     }
 
-    fn from_atom(settings: &Settings, feed: &atom_syndication::Feed) -> Self {
-        let mut messages = Messages { vec: Vec::new() };
+    fn from_atom(feed: &atom_syndication::Feed) -> Self {
+        let sy = |name| {
+            feed.extensions()
+                .get("sy")
+                .and_then(|sy| sy.get(name))
+                .and_then(|values| values.first())
+                .and_then(|value| value.value())
+        };
+        let mut messages = Messages {
+            format: "Atom",
+            feed_name: feed.title().to_string(),
+            vec: Vec::new(),
+            interval: update_interval(sy("updatePeriod"), sy("updateFrequency")),
+        };
         for entry in feed.entries().iter() {
             let id = entry.id().clone();
             let text = "";
@@ -111,22 +271,60 @@ impl Messages {
                 None => "",
             };
 
-            let email = Messages::build_message(&settings, feed.title(), entry.title(), link, text);
-
-            messages.vec.push((id.to_string(), email));
+            messages.vec.push(Entry {
+                                  id: id.to_string(),
+                                  title: entry.title().to_string(),
+                                  url: link.to_string(),
+                                  body: text.to_string(),
+                                  author: entry.authors()
+                                      .iter()
+                                      .map(|author| author.name())
+                                      .collect::<Vec<_>>()
+                                      .join(", "),
+                                  categories: entry.categories()
+                                      .iter()
+                                      .map(|category| category.term().to_string())
+                                      .collect(),
+                                  to: None,
+                                  headers: Vec::new(),
+                              });
         }
         messages
     }
 
-    pub fn new(settings: &Settings, data: &str) -> Result<Self> {
+    pub fn new(data: &str) -> Result<Self> {
         match atom_syndication::Feed::read_from(data.as_bytes()) {
-            Ok(feed) => Ok(Messages::from_atom(&settings, &feed)),
+            Ok(feed) => Ok(Messages::from_atom(&feed)),
             _ => {
                 match rss::Channel::read_from(data.as_bytes()) {
-                    Ok(channel) => Ok(Messages::from_rss(&settings, &channel)),
+                    Ok(channel) => Ok(Messages::from_rss(&channel)),
                     _ => Err("Could not parse as RSS or Atom".into()),
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rss_ids_fall_back_to_link_then_content() {
+        let feed = r#"<?xml version="1.0"?>
+<rss version="2.0"><channel><title>Feed</title><link>https://example.org/</link>
+<description>Feed</description>
+<item><title>One</title><guid>tag:example.org,2017:1</guid><link>https://example.org/1</link></item>
+<item><title>Two</title><link>https://example.org/2</link></item>
+<item><title>Three</title><description>third</description></item>
+<item><title>Four</title><description>fourth</description></item>
+</channel></rss>"#;
+        let messages = Messages::new(feed).ok().expect("valid feed");
+        let ids: Vec<&str> = messages.vec.iter().map(|entry| entry.id.as_str()).collect();
+        assert_eq!(ids[0], "tag:example.org,2017:1");
+        assert_eq!(ids[1], "https://example.org/2");
+        assert!(!ids[2].is_empty());
+        assert!(ids[2] != ids[3]);
+        assert_eq!(ids[2], content_id("Three", "third"));
+    }
+}
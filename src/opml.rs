@@ -1,95 +1,371 @@
 use xml::{reader, writer};
+use xml::attribute::OwnedAttribute;
 use feeds;
-use std::fs::{File, OpenOptions};
-use std::io::BufReader;
-use std::collections::HashMap;
+use filter::{Filter, FilterAction, FilterField};
+use http;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
 use errors::*;
 
-fn get_map(path: &str) -> Result<HashMap<String, String>> {
-    let mut hashmap = HashMap::new();
-    let file = File::open(path).unwrap();
+/// A feed of an OPML file.
+struct Outline {
+    title: String,
+    url: String,
+    /// enclosing folders, separated by `/`
+    category: Option<String>,
+    /// rust2email attributes, without their namespace
+    attributes: Vec<(String, String)>,
+    /// entry filters, given by `rust2email:filter` elements
+    filters: Vec<Filter>,
+}
+
+/// Filter given by the attributes of a `rust2email:filter` element.
+fn get_filter(attributes: &[OwnedAttribute]) -> Result<Filter> {
+    let get = |name: &str| {
+        attributes
+            .iter()
+            .find(|attribute| attribute.name.local_name == name)
+            .map(|attribute| attribute.value.as_str())
+    };
+    let action = FilterAction::from_str(get("action").unwrap_or(""))?;
+    let field = FilterField::from_str(get("field").unwrap_or("any"))?;
+    Filter::new(action,
+                field,
+                get("pattern").unwrap_or(""),
+                get("regex") == Some("true"))
+}
+
+fn get_outlines(path: &str) -> Result<Vec<Outline>> {
+    let mut outlines: Vec<Outline> = Vec::new();
+    // the folder name of each open outline, `None` for the feeds
+    let mut folders: Vec<Option<String>> = Vec::new();
+    let file = File::open(path).chain_err(|| format!("could not open {}", path))?;
     let file = BufReader::new(file);
 
     let parser = reader::EventReader::new(file);
     for e in parser {
         match e {
             Ok(reader::XmlEvent::StartElement { name, attributes, .. }) => {
-                if name.local_name == "outline" {
+                if name.namespace.as_ref().map(|ns| ns.as_str()) == Some(NS) &&
+                   name.local_name == "filter" {
+                    // only feeds have filters
+                    if let (Some(&None), Some(outline)) = (folders.last(), outlines.last_mut()) {
+                        let filter = get_filter(&attributes)
+                            .chain_err(|| format!("invalid filter for {}", outline.title))?;
+                        outline.filters.push(filter);
+                    }
+                } else if name.local_name == "outline" {
                     let mut title = String::new();
                     let mut url = String::new();
+                    let mut rust2email = Vec::new();
                     for attribute in attributes {
+                        if attribute.name.namespace.as_ref().map(|ns| ns.as_str()) == Some(NS) {
+                            rust2email.push((attribute.name.local_name, attribute.value));
+                            continue;
+                        }
                         match attribute.name.local_name.as_str() {
                             "text" => title = attribute.value,
+                            "title" if title.is_empty() => title = attribute.value,
                             "xmlUrl" => url = attribute.value,
                             _ => {}
                         }
                     }
-                    hashmap.insert(title, url);
+                    if url.is_empty() {
+                        folders.push(Some(title));
+                        continue;
+                    }
+                    let category = folders
+                        .iter()
+                        .filter_map(|folder| folder.as_ref())
+                        .map(|folder| folder.as_str())
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    folders.push(None);
+                    outlines.push(Outline {
+                                      title: title,
+                                      url: url,
+                                      category: if category.is_empty() {
+                                          None
+                                      } else {
+                                          Some(category)
+                                      },
+                                      attributes: rust2email,
+                                      filters: Vec::new(),
+                                  });
                 }
             }
-            Err(e) => {
-                println!("Error: {}", e);
-                break;
+            Ok(reader::XmlEvent::EndElement { name }) => {
+                if name.local_name == "outline" {
+                    folders.pop();
+                }
             }
+            Err(e) => bail!("could not parse {}: {}", path, e),
             _ => {}
         }
     }
-    Ok(hashmap)
+    Ok(outlines)
+}
+
+/// What to do with the feeds of an OPML file which are already in the database.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// only add the new feeds
+    Add,
+    /// update the category and options of the feeds with the same URL
+    Merge,
+    /// replace the feeds with the same URL or name by the ones of the file
+    Replace,
+}
+
+/// Set the category, state, options and filters of `feed` from `outline`.
+///
+/// The category and the paused state are always the ones of the file, so
+/// that merging an exported file gives the feeds back as they were.
+fn apply(feed: &mut feeds::Feed, outline: &Outline) -> Result<()> {
+    feed.category = outline.category.clone();
+    if feed.paused && !outline.attributes.iter().any(|&(ref name, _)| name == "paused") {
+        feed.paused = false;
+        feed.paused_reason = None;
+    }
+    if !outline.filters.is_empty() {
+        feed.filters = outline.filters.clone();
+    }
+    for &(ref name, ref value) in &outline.attributes {
+        // attributes of newer versions are left out
+        if feeds::SETTINGS.contains(&name.as_str()) {
+            feed.set(name, value)
+                .chain_err(|| format!("invalid attribute for {}", outline.title))?;
+        }
+    }
+    Ok(())
+}
+
+/// Replace `feed` by `outline`, the seen entries are kept when the URL
+/// does not change.
+fn replace(feed: &mut feeds::Feed, outline: &Outline, name: bool) -> Result<()> {
+    if name {
+        feed.name = outline.title.clone();
+    }
+    if http::normalize(&feed.url) != http::normalize(&outline.url) {
+        feed.seen.clear();
+    }
+    feed.url = outline.url.clone();
+    feed.paused = false;
+    feed.paused_reason = None;
+    feed.options = feeds::FeedOptions::default();
+    feed.category = None;
+    feed.filters.clear();
+    apply(feed, outline)
+}
+
+/// Import the feeds of an OPML file, matched with the existing ones by URL.
+///
+/// Every feed of the file is reported, with `dry_run` or when one of them
+/// is invalid the database is left untouched.
+pub fn import(feeds: &mut feeds::Feeds, path: &str, mode: Mode, dry_run: bool) -> Result<()> {
+    let outlines = get_outlines(path)?;
+    // the feeds are imported even for a dry run, to check all of them, and
+    // put back when the import is not kept
+    let original = feeds.feeds.clone();
+    let result = import_outlines(feeds, path, outlines, mode);
+    if dry_run || result.is_err() {
+        feeds.feeds = original;
+    }
+    if result.is_err() {
+        println!("nothing was imported from {}", path);
+    } else if dry_run {
+        println!("dry run, nothing was changed");
+    }
+    result
 }
 
-pub fn import(feeds: &mut feeds::Feeds, path: &str) {
-    let mut hashmap = get_map(path).unwrap();
+fn import_outlines(feeds: &mut feeds::Feeds,
+                   path: &str,
+                   outlines: Vec<Outline>,
+                   mode: Mode)
+                   -> Result<()> {
+    // name and normalized URL of the feeds of the file already handled
+    let mut handled: Vec<(String, String)> = Vec::new();
+
+    for outline in outlines {
+        let url = http::normalize(&outline.url);
+        if let Some(&(ref name, _)) = handled.iter().find(|&&(_, ref u)| u == &url) {
+            println!("{}: skipped, same URL as {} in {}", outline.title, name, path);
+            continue;
+        }
+        if handled.iter().any(|&(ref name, _)| name == &outline.title) {
+            println!("{}: skipped, name used twice in {}", outline.title, path);
+            continue;
+        }
+        handled.push((outline.title.clone(), url.clone()));
 
-    for ref feed in &feeds.feeds {
-        if hashmap.contains_key(feed.name.as_str()) {
-            hashmap.remove(feed.name.as_str());
+        let by_url = feeds.feeds.iter().position(|feed| http::normalize(&feed.url) == url);
+        let by_name = feeds.feeds.iter().position(|feed| feed.name == outline.title);
+        match (by_url, by_name) {
+            (None, None) => {
+                println!("{}: added ({})", outline.title, outline.url);
+                let feed = feeds.push(&outline.title, &outline.url);
+                apply(feed, &outline)?;
+            }
+            (Some(index), _) => {
+                let existing = feeds.feeds[index].name.clone();
+                match mode {
+                    Mode::Add => println!("{}: skipped, same URL as {}", outline.title, existing),
+                    Mode::Merge => {
+                        println!("{}: merged into {}", outline.title, existing);
+                        apply(&mut feeds.feeds[index], &outline)?;
+                    }
+                    Mode::Replace => {
+                        // the name can only be taken if it is free
+                        let rename = by_name.map_or(true, |other| other == index);
+                        if rename {
+                            println!("{}: replaces {}", outline.title, existing);
+                        } else {
+                            println!("{}: replaces {}, keeping its name since {} is used",
+                                     outline.title,
+                                     existing,
+                                     outline.title);
+                        }
+                        replace(&mut feeds.feeds[index], &outline, rename)?;
+                    }
+                }
+            }
+            (None, Some(index)) => {
+                let existing = feeds.feeds[index].url.clone();
+                match mode {
+                    Mode::Replace => {
+                        println!("{}: replaces {} by {}", outline.title, existing, outline.url);
+                        replace(&mut feeds.feeds[index], &outline, true)?;
+                    }
+                    _ => {
+                        println!("{}: skipped, name already used for {}",
+                                 outline.title,
+                                 existing)
+                    }
+                }
+            }
         }
     }
+    Ok(())
+}
+
+/// Namespace of the rust2email attributes, which keep the state and the
+/// options of the feeds.
+const NS: &str = "https://github.com/nurelin/rust2email";
+
+/// rust2email attributes of `feed`.
+fn attributes(feed: &feeds::Feed) -> Vec<(&'static str, String)> {
+    let mut attributes = Vec::new();
+    if feed.paused {
+        attributes.push(("rust2email:paused", "true".to_string()));
+    }
+    let options = &feed.options;
+    if options.fetch_full_content {
+        attributes.push(("rust2email:full", "true".to_string()));
+    }
+    if let Some(digest) = options.digest {
+        attributes.push(("rust2email:digest", digest.as_str().to_string()));
+    }
+    if let Some(ref hook) = options.hook {
+        attributes.push(("rust2email:hook", hook.clone()));
+    }
+    if let Some(ref command) = options.command {
+        attributes.push(("rust2email:command", command.clone()));
+    }
+    if let Some(max_per_run) = options.max_per_run {
+        attributes.push(("rust2email:max-per-run", max_per_run.to_string()));
+    }
+    if let Some(interval) = options.interval {
+        attributes.push(("rust2email:interval", interval.to_string()));
+    }
+    if let Some(ref to) = options.to {
+        attributes.push(("rust2email:to", to.clone()));
+    }
+    attributes
+}
 
-    for (name, url) in hashmap {
-        feeds.push(name.as_str(), url.as_str());
+/// Folders of the category of `feed`, from the outermost one.
+fn category_folders(feed: &feeds::Feed) -> Vec<&str> {
+    match feed.category {
+        Some(ref category) => category.split('/').collect(),
+        None => Vec::new(),
     }
 }
 
-pub fn export(feeds: &mut feeds::Feeds, path: &str) {
-    let file = OpenOptions::new()
-        .write(true)
-        .create_new(true)
-        .open(path)
-        .unwrap();
+/// Write the feeds to `path`, or to the standard output for `-`.
+pub fn export(feeds: &feeds::Feeds, path: &str) -> Result<()> {
+    let output: Box<dyn Write> = if path == "-" {
+        Box::new(io::stdout())
+    } else {
+        Box::new(File::create(path).chain_err(|| format!("could not create {}", path))?)
+    };
     let mut writer = writer::EmitterConfig::new()
         .perform_indent(true)
-        .create_writer(file);
+        .create_writer(output);
 
     writer
-        .write(writer::XmlEvent::start_element("opml").attr("version", "2.0"))
-        .unwrap();
-    writer
-        .write(writer::XmlEvent::start_element("head"))
-        .unwrap();
-    writer
-        .write(writer::XmlEvent::start_element("title"))
-        .unwrap();
-    writer
-        .write(writer::XmlEvent::characters("rust2email OPML export"))
-        .unwrap();
-    writer.write(writer::XmlEvent::end_element()).unwrap();
-    writer.write(writer::XmlEvent::end_element()).unwrap();
-    writer
-        .write(writer::XmlEvent::start_element("body"))
-        .unwrap();
+        .write(writer::XmlEvent::start_element("opml")
+                   .attr("version", "2.0")
+                   .ns("rust2email", NS))?;
+    writer.write(writer::XmlEvent::start_element("head"))?;
+    writer.write(writer::XmlEvent::start_element("title"))?;
+    writer.write(writer::XmlEvent::characters("rust2email OPML export"))?;
+    writer.write(writer::XmlEvent::end_element())?;
+    writer.write(writer::XmlEvent::end_element())?;
+    writer.write(writer::XmlEvent::start_element("body"))?;
 
-    for feed in &feeds.feeds {
-        writer
-            .write(writer::XmlEvent::start_element("outline")
-                       .attr("title", feed.name.as_str())
-                       .attr("text", feed.name.as_str())
-                       .attr("xmlUrl", feed.url.as_str()))
-            .unwrap();
-        writer.write(writer::XmlEvent::end_element()).unwrap();
-    }
+    // the feeds of a folder are written together, in the database order
+    let mut sorted: Vec<&feeds::Feed> = feeds.feeds.iter().collect();
+    sorted.sort_by(|a, b| category_folders(a).cmp(&category_folders(b)));
 
-    writer.write(writer::XmlEvent::end_element()).unwrap();
-    writer.write(writer::XmlEvent::end_element()).unwrap();
+    // folders of the categories, opened and closed as the feeds go
+    let mut folders: Vec<&str> = Vec::new();
+    for feed in sorted {
+        let category = category_folders(feed);
+        let common = folders
+            .iter()
+            .zip(&category)
+            .take_while(|&(a, b)| a == b)
+            .count();
+        while folders.len() > common {
+            writer.write(writer::XmlEvent::end_element())?;
+            folders.pop();
+        }
+        for folder in &category[common..] {
+            writer
+                .write(writer::XmlEvent::start_element("outline")
+                           .attr("title", folder)
+                           .attr("text", folder))?;
+            folders.push(folder);
+        }
+
+        let attributes = attributes(feed);
+        let mut outline = writer::XmlEvent::start_element("outline")
+            .attr("title", feed.name.as_str())
+            .attr("text", feed.name.as_str())
+            .attr("xmlUrl", feed.url.as_str());
+        for &(name, ref value) in &attributes {
+            outline = outline.attr(name, value.as_str());
+        }
+        writer.write(outline)?;
+        for filter in &feed.filters {
+            let mut element = writer::XmlEvent::start_element("rust2email:filter")
+                .attr("action", filter.action.as_str())
+                .attr("field", filter.field.as_str())
+                .attr("pattern", filter.pattern.as_str());
+            if filter.regex {
+                element = element.attr("regex", "true");
+            }
+            writer.write(element)?;
+            writer.write(writer::XmlEvent::end_element())?;
+        }
+        writer.write(writer::XmlEvent::end_element())?;
+    }
+    for _ in folders {
+        writer.write(writer::XmlEvent::end_element())?;
+    }
 
+    writer.write(writer::XmlEvent::end_element())?;
+    writer.write(writer::XmlEvent::end_element())?;
+    Ok(())
 }
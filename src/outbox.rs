@@ -0,0 +1,57 @@
+use lettre::{EmailAddress, SendableEmail, SimpleSendableEmail};
+use lettre_email::Email;
+
+/// An email ready to be sent, kept in the database until it is delivered.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OutgoingEmail {
+    pub from: String,
+    pub to: Vec<String>,
+    pub message_id: String,
+    pub message: String,
+    /// feed name and id of the entries in this email, marked seen once it is delivered
+    pub entries: Vec<(String, String)>,
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub last_error: String,
+}
+
+impl OutgoingEmail {
+    pub fn new(email: &Email, entries: Vec<(String, String)>) -> Self {
+        OutgoingEmail {
+            from: email.from().0,
+            to: email.to().into_iter().map(|to| to.0).collect(),
+            message_id: email.message_id(),
+            message: String::from_utf8_lossy(*email.message()).into_owned(),
+            entries: entries,
+            attempts: 0,
+            last_error: String::new(),
+        }
+    }
+
+    pub fn sendable(&self) -> SimpleSendableEmail {
+        SimpleSendableEmail::new(EmailAddress::new(self.from.clone()),
+                                 self.to
+                                     .iter()
+                                     .map(|to| EmailAddress::new(to.clone()))
+                                     .collect(),
+                                 self.message_id.clone(),
+                                 self.message.clone())
+    }
+
+    pub fn contains(&self, feed_name: &str, id: &str) -> bool {
+        self.entries.iter().any(|&(ref feed, ref entry)| feed == feed_name && entry == id)
+    }
+
+    pub fn subject(&self) -> &str {
+        for line in self.message.lines() {
+            if line.is_empty() {
+                break;
+            }
+            if line.starts_with("Subject: ") {
+                return &line["Subject: ".len()..];
+            }
+        }
+        ""
+    }
+}
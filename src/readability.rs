@@ -0,0 +1,283 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::default::Default;
+use html5ever::{parse_document, serialize};
+use html5ever::rcdom::{Element, Handle, Node, RcDom, Text};
+use html5ever::serialize::SerializeOpts;
+use html5ever::tendril::TendrilSink;
+use http;
+use errors::*;
+
+// paragraphs shorter than this are navigation, captions and the like
+const MIN_PARAGRAPH_LENGTH: usize = 25;
+// below this the best candidate is most likely not the article
+const MIN_ARTICLE_LENGTH: usize = 250;
+
+const SKIPPED_TAGS: &'static [&'static str] = &["script", "style", "noscript", "nav", "header",
+                                                 "footer", "aside", "form", "iframe", "button"];
+const UNLIKELY: &'static [&'static str] = &["comment", "sidebar", "footer", "menu", "nav",
+                                             "share", "social", "sponsor", "banner", "popup",
+                                             "related", "advert", "promo"];
+const POSITIVE: &'static [&'static str] = &["article", "body", "content", "entry", "main",
+                                             "page", "post", "story", "text"];
+const NEGATIVE: &'static [&'static str] = &["combx", "comment", "contact", "foot", "footer",
+                                             "footnote", "masthead", "meta", "outbrain",
+                                             "shoutbox", "sidebar", "widget"];
+
+struct Candidate {
+    handle: Handle,
+    score: f64,
+}
+
+fn key(handle: &Handle) -> *const RefCell<Node> {
+    &***handle
+}
+
+fn tag_name(handle: &Handle) -> Option<String> {
+    match handle.borrow().node {
+        Element(ref name, _, _) => Some(name.local.to_lowercase()),
+        _ => None,
+    }
+}
+
+fn class_and_id(handle: &Handle) -> String {
+    let mut names = String::new();
+    if let Element(_, _, ref attrs) = handle.borrow().node {
+        for attr in attrs {
+            match &*attr.name.local {
+                "class" | "id" => {
+                    names.push_str(&attr.value.to_lowercase());
+                    names.push(' ');
+                }
+                _ => {}
+            }
+        }
+    }
+    names
+}
+
+fn matches_any(names: &str, patterns: &[&str]) -> bool {
+    patterns.iter().any(|pattern| names.contains(pattern))
+}
+
+fn class_weight(handle: &Handle) -> f64 {
+    let names = class_and_id(handle);
+    let mut weight = 0.0;
+    if matches_any(&names, NEGATIVE) {
+        weight -= 25.0;
+    }
+    if matches_any(&names, POSITIVE) {
+        weight += 25.0;
+    }
+    weight
+}
+
+fn base_score(handle: &Handle) -> f64 {
+    let score = match tag_name(handle).as_ref().map(|s| s.as_str()) {
+        Some("div") => 5.0,
+        Some("pre") | Some("td") | Some("blockquote") => 3.0,
+        Some("address") | Some("ol") | Some("ul") | Some("dl") | Some("dd") | Some("dt") |
+        Some("li") | Some("form") => -3.0,
+        Some("h1") | Some("h2") | Some("h3") | Some("h4") | Some("h5") | Some("h6") |
+        Some("th") => -5.0,
+        _ => 0.0,
+    };
+    score + class_weight(handle)
+}
+
+fn text_content(handle: &Handle, text: &mut String) {
+    let node = handle.borrow();
+    match node.node {
+        Text(ref content) => text.push_str(content),
+        _ => {
+            for child in node.children.iter() {
+                text_content(child, text);
+            }
+        }
+    }
+}
+
+fn link_text_length(handle: &Handle) -> usize {
+    if tag_name(handle).as_ref().map(|s| s.as_str()) == Some("a") {
+        let mut text = String::new();
+        text_content(handle, &mut text);
+        return text.trim().len();
+    }
+    handle
+        .borrow()
+        .children
+        .iter()
+        .map(|child| link_text_length(child))
+        .sum()
+}
+
+fn is_skipped(handle: &Handle) -> bool {
+    match tag_name(handle) {
+        Some(tag) => {
+            if SKIPPED_TAGS.contains(&tag.as_str()) {
+                return true;
+            }
+            let names = class_and_id(handle);
+            tag != "body" && matches_any(&names, UNLIKELY) && !matches_any(&names, POSITIVE)
+        }
+        None => false,
+    }
+}
+
+fn score_paragraphs(handle: &Handle,
+                    ancestors: &mut Vec<Handle>,
+                    candidates: &mut HashMap<*const RefCell<Node>, Candidate>) {
+    if is_skipped(handle) {
+        return;
+    }
+
+    match tag_name(handle).as_ref().map(|s| s.as_str()) {
+        Some("p") | Some("pre") | Some("td") => {
+            let mut text = String::new();
+            text_content(handle, &mut text);
+            let text = text.trim();
+            if text.len() >= MIN_PARAGRAPH_LENGTH {
+                let score = 1.0 + text.matches(',').count() as f64 +
+                            (text.len() as f64 / 100.0).min(3.0);
+                for (distance, ancestor) in ancestors.iter().rev().take(2).enumerate() {
+                    let candidate = candidates
+                        .entry(key(ancestor))
+                        .or_insert_with(|| {
+                                            Candidate {
+                                                handle: ancestor.clone(),
+                                                score: base_score(ancestor),
+                                            }
+                                        });
+                    candidate.score += score / (distance + 1) as f64;
+                }
+            }
+        }
+        _ => {}
+    }
+
+    ancestors.push(handle.clone());
+    for child in handle.borrow().children.iter() {
+        score_paragraphs(child, ancestors, candidates);
+    }
+    ancestors.pop();
+}
+
+/// Remove the scripts, forms, sidebars and the like from `handle`.
+fn clean(handle: &Handle) {
+    handle.borrow_mut().children.retain(|child| !is_skipped(child));
+    let children = handle.borrow().children.clone();
+    for child in children.iter() {
+        clean(child);
+    }
+}
+
+/// Extract the main content of an HTML page, serialized as HTML.
+///
+/// This is a simplified version of the readability algorithm: every
+/// paragraph gives points to its parent and grandparent, the element
+/// with the best score, weighted by its link density, is the article.
+pub fn extract(html: &str) -> Option<String> {
+    let dom = parse_document(RcDom::default(), Default::default()).one(html);
+
+    let mut candidates = HashMap::new();
+    score_paragraphs(&dom.document, &mut Vec::new(), &mut candidates);
+
+    let mut best: Option<(Handle, f64)> = None;
+    for (_, candidate) in candidates {
+        let mut text = String::new();
+        text_content(&candidate.handle, &mut text);
+        let length = text.trim().len();
+        if length == 0 {
+            continue;
+        }
+        let link_density = link_text_length(&candidate.handle) as f64 / length as f64;
+        let score = candidate.score * (1.0 - link_density);
+        let better = match best {
+            Some((_, best_score)) => score > best_score,
+            None => true,
+        };
+        if better {
+            best = Some((candidate.handle, score));
+        }
+    }
+
+    let article = match best {
+        Some((handle, _)) => handle,
+        None => return None,
+    };
+
+    let mut text = String::new();
+    text_content(&article, &mut text);
+    if text.trim().len() < MIN_ARTICLE_LENGTH {
+        return None;
+    }
+
+    clean(&article);
+    let mut bytes = Vec::new();
+    if serialize(&mut bytes, &article, SerializeOpts::default()).is_err() {
+        return None;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Download `url` and extract its main content.
+pub fn fetch(url: &str) -> Result<String> {
+    let page = http::get(url)?;
+    match extract(&page) {
+        Some(article) => Ok(article),
+        None => bail!("no article found in {}", url),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAGRAPH: &str = "<p>This paragraph is long enough to count, with a few commas, \
+                             some words, and a sentence that goes on for a while.</p>";
+
+    fn page(article: &str) -> String {
+        format!("<html><head><title>Page</title><style>body {{}}</style></head><body>\
+                 <nav><a href=\"/\">Home</a> <a href=\"/about\">About the website</a></nav>\
+                 <div class=\"sidebar\"><p>Related: another story which is not the article.</p></div>\
+                 <div class=\"post\">{}</div>\
+                 <footer><p>Copyright notice which is long enough to be a paragraph.</p></footer>\
+                 </body></html>",
+                article)
+    }
+
+    #[test]
+    fn extract_article() {
+        let html = page(&format!("<h1>Title</h1>{}{}{}{}", PARAGRAPH, PARAGRAPH, PARAGRAPH, PARAGRAPH));
+        let article = extract(&html).expect("no article");
+        // the content of the article, without its container
+        assert!(article.starts_with("<h1>Title</h1><p>"));
+        assert_eq!(article.matches("This paragraph").count(), 4);
+        assert!(!article.contains("Home"));
+        assert!(!article.contains("Related"));
+        assert!(!article.contains("Copyright"));
+    }
+
+    #[test]
+    fn extract_removes_skipped_elements() {
+        let html = page(&format!("{}<script>alert(1)</script>{}<form><input name=\"q\"></form>\
+                                  {}<iframe src=\"https://ads.invalid\"></iframe>\
+                                  <div class=\"share\"><a href=\"/share\">Share this</a></div>{}",
+                                 PARAGRAPH,
+                                 PARAGRAPH,
+                                 PARAGRAPH,
+                                 PARAGRAPH));
+        let article = extract(&html).expect("no article");
+        assert_eq!(article.matches("This paragraph").count(), 4);
+        for removed in &["<script", "alert", "<form", "<input", "<iframe", "Share this"] {
+            assert!(!article.contains(removed), "{} in {}", removed, article);
+        }
+    }
+
+    #[test]
+    fn extract_nothing_from_short_pages() {
+        assert_eq!(extract(&page(PARAGRAPH)), None);
+        assert_eq!(extract("<html><body><p>Hello</p></body></html>"), None);
+        assert_eq!(extract(""), None);
+    }
+}
@@ -0,0 +1,203 @@
+use serde_json::{self, Value};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use toml;
+use xdg;
+use feeds::Feeds;
+use settings::DigestMode;
+use errors::*;
+
+/// Sections of an INI file with their `key = value` pairs.
+type Ini = Vec<(String, Vec<(String, String)>)>;
+
+fn parse_ini(data: &str) -> Ini {
+    let mut sections: Ini = vec![("DEFAULT".to_string(), Vec::new())];
+    for line in data.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+            continue;
+        }
+        let values = &mut sections.last_mut().unwrap().1;
+        if line.starts_with(|c: char| c.is_whitespace()) && !values.is_empty() {
+            // continuation of the previous value
+            let value = &mut values.last_mut().unwrap().1;
+            value.push('\n');
+            value.push_str(trimmed);
+            continue;
+        }
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            let name = trimmed[1..trimmed.len() - 1].trim().to_string();
+            sections.push((name, Vec::new()));
+            continue;
+        }
+        if let Some(position) = trimmed.find(|c| c == '=' || c == ':') {
+            let key = trimmed[..position].trim().to_lowercase();
+            let value = trimmed[position + 1..].trim().to_string();
+            values.push((key, value));
+        }
+    }
+    sections
+}
+
+fn get<'a>(values: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    values
+        .iter()
+        .rev()
+        .find(|&&(ref k, _)| k == key)
+        .map(|&(_, ref v)| v.as_str())
+}
+
+fn get_bool(values: &[(String, String)], key: &str) -> Option<bool> {
+    match get(values, key).map(|value| value.to_lowercase()) {
+        Some(value) => {
+            match value.as_str() {
+                "1" | "yes" | "true" | "on" => Some(true),
+                "0" | "no" | "false" | "off" => Some(false),
+                _ => None,
+            }
+        }
+        None => None,
+    }
+}
+
+fn read(path: &Path) -> Result<String> {
+    let mut f = File::open(path).chain_err(|| format!("could not open {}", path.display()))?;
+    let mut data = String::new();
+    f.read_to_string(&mut data)?;
+    Ok(data)
+}
+
+/// Location of the rss2email configuration file, `rss2email.cfg`.
+pub fn config_file(path: Option<&str>) -> Result<PathBuf> {
+    match path {
+        Some(path) => Ok(path.into()),
+        None => {
+            let xdg_dirs = xdg::BaseDirectories::new().unwrap();
+            match xdg_dirs.find_config_file("rss2email.cfg") {
+                Some(path) => Ok(path),
+                None => bail!("no rss2email.cfg found"),
+            }
+        }
+    }
+}
+
+/// Location of the rss2email database, `rss2email.json`.
+pub fn data_file(path: Option<&str>) -> Result<PathBuf> {
+    match path {
+        Some(path) => Ok(path.into()),
+        None => {
+            let xdg_dirs = xdg::BaseDirectories::new().unwrap();
+            match xdg_dirs.find_data_file("rss2email.json") {
+                Some(path) => Ok(path),
+                None => bail!("no rss2email.json found"),
+            }
+        }
+    }
+}
+
+/// Translate the `[DEFAULT]` section of `rss2email.cfg` to a rust2email configuration.
+pub fn config(path: &Path) -> Result<String> {
+    let ini = parse_ini(&read(path)?);
+    let defaults = &ini[0].1;
+
+    let mut config = toml::value::Table::new();
+    match get(defaults, "to") {
+        Some(to) if !to.is_empty() => {
+            config.insert("to".into(), toml::Value::String(to.into()));
+        }
+        _ => {
+            eprintln!("no recipient in {}, set `to` in the configuration", path.display());
+            config.insert("to".into(), toml::Value::String("postmaster@invalid".into()));
+        }
+    }
+    if let Some(from) = get(defaults, "from") {
+        config.insert("from_address".into(), toml::Value::String(from.into()));
+    }
+    // rss2email sends text emails unless told otherwise
+    if !get_bool(defaults, "html-mail").unwrap_or(false) {
+        config.insert("text".into(), toml::Value::Boolean(true));
+        if let Some(Ok(width)) = get(defaults, "body-width").map(|width| width.parse::<i64>()) {
+            if width > 0 {
+                config.insert("text_wrap".into(), toml::Value::Integer(width));
+            }
+        }
+    }
+    if get_bool(defaults, "digest").unwrap_or(false) {
+        config.insert("digest".into(), toml::Value::String("feed".into()));
+    }
+    match get(defaults, "email-protocol") {
+        None | Some("sendmail") => {}
+        Some(protocol) => eprintln!("{} is not supported, sendmail is used instead", protocol),
+    }
+    config.insert("mail_backend".into(), toml::Value::String("sendmail".into()));
+    if let Some(sendmail) = get(defaults, "sendmail") {
+        let mut mail_sendmail = toml::value::Table::new();
+        mail_sendmail.insert("path".into(), toml::Value::String(sendmail.into()));
+        config.insert("mail_sendmail".into(), toml::Value::Table(mail_sendmail));
+    }
+
+    toml::to_string(&toml::Value::Table(config)).chain_err(|| "could not write the configuration")
+}
+
+/// Add the feeds of `rss2email.cfg` to `feeds`, with the ids already
+/// seen by rss2email taken from its database.
+pub fn import(config: &Path, data: &Path, feeds: &mut Feeds) -> Result<()> {
+    let ini = parse_ini(&read(config)?);
+    let defaults = &ini[0].1;
+
+    let data: Value = serde_json::from_str(&read(data)?)
+        .chain_err(|| format!("could not parse {}", data.display()))?;
+    let empty = Vec::new();
+    let states = data.get("feeds").and_then(Value::as_array).unwrap_or(&empty);
+
+    for &(ref section, ref values) in &ini[1..] {
+        if !section.starts_with("feed.") {
+            continue;
+        }
+        let name = &section["feed.".len()..];
+        let url = match get(values, "url") {
+            Some(url) => url,
+            None => {
+                eprintln!("{} has no url, skipped", name);
+                continue;
+            }
+        };
+        if feeds.contains(name) {
+            eprintln!("{} already exists, skipped", name);
+            continue;
+        }
+        if get(values, "post-process").is_some() {
+            eprintln!("{}: post-process is not supported, use a hook or a command", name);
+        }
+
+        let feed = feeds.push(name, url);
+        let active = get_bool(values, "active").or(get_bool(defaults, "active")).unwrap_or(true);
+        feed.paused = !active;
+        if let Some(to) = get(values, "to") {
+            feed.options.to = Some(to.to_string());
+        }
+        if let Some(digest) = get_bool(values, "digest") {
+            feed.options.digest = Some(if digest {
+                                           DigestMode::Feed
+                                       } else {
+                                           DigestMode::None
+                                       });
+        }
+
+        // the seen entries are either a list of ids or a map keyed by id
+        let state = states
+            .iter()
+            .find(|state| state.get("name").and_then(Value::as_str) == Some(name));
+        match state.and_then(|state| state.get("seen")) {
+            Some(&Value::Object(ref seen)) => feed.seen.extend(seen.keys().cloned()),
+            Some(&Value::Array(ref seen)) => {
+                feed.seen
+                    .extend(seen.iter().filter_map(Value::as_str).map(|id| id.to_string()))
+            }
+            _ => {}
+        }
+        println!("{}: {} ({} seen)", name, url, feed.seen.len());
+    }
+    Ok(())
+}
@@ -1,5 +1,9 @@
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::Read;
+use std::path::PathBuf;
+use std::time::Duration as StdDuration;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, Weekday};
 use toml;
 use xdg;
 use errors::*;
@@ -15,6 +19,13 @@ struct ConfigFileMailSendMail {
     path: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct ConfigFileCategory {
+    to: Option<String>,
+    digest: Option<String>,
+    headers: Option<BTreeMap<String, String>>,
+}
+
 #[derive(Debug, Deserialize)]
 struct ConfigFileSettings {
     verbose: Option<bool>,
@@ -25,16 +36,153 @@ struct ConfigFileSettings {
     to: String,
     subject: Option<String>,
     body: Option<String>,
+    digest: Option<String>,
+    digest_subject: Option<String>,
+    digest_body: Option<String>,
+    digest_toc_entry: Option<String>,
+    digest_entry: Option<String>,
+    digest_schedule: Option<String>,
+    digest_time: Option<String>,
+    digest_day: Option<String>,
+    hook: Option<String>,
+    command_timeout: Option<u64>,
+    send_rate: Option<String>,
+    max_per_feed: Option<usize>,
+    interval: Option<u64>,
+    update_moved_feeds: Option<bool>,
+    alert_failures: Option<u64>,
+    alert_to: Option<String>,
+    storage: Option<String>,
+    categories: Option<HashMap<String, ConfigFileCategory>>,
     mail_backend: String,
     mail_file: Option<ConfigFileMailFile>,
     mail_sendmail: Option<ConfigFileMailSendMail>
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestMode {
+    /// one email per entry
+    None,
+    /// one email per feed
+    Feed,
+    /// one email for all the feeds
+    All,
+}
+
+impl DigestMode {
+    pub fn from_str(mode: &str) -> Result<Self> {
+        match mode {
+            "none" => Ok(DigestMode::None),
+            "feed" => Ok(DigestMode::Feed),
+            "all" => Ok(DigestMode::All),
+            _ => bail!("unknown digest mode {}, expected none, feed or all", mode),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            DigestMode::None => "none",
+            DigestMode::Feed => "feed",
+            DigestMode::All => "all",
+        }
+    }
+}
+
+pub enum DigestSchedule {
+    /// send the digests at the end of each run
+    EveryRun,
+    Daily { time: NaiveTime },
+    Weekly { day: Weekday, time: NaiveTime },
+}
+
+impl DigestSchedule {
+    fn new(schedule: Option<&str>, time: Option<&str>, day: Option<&str>) -> Result<Self> {
+        let time = match time {
+            Some(time) => match NaiveTime::parse_from_str(time, "%H:%M") {
+                Ok(time) => time,
+                Err(e) => bail!("invalid digest_time {}: {}", time, e),
+            },
+            None => NaiveTime::from_hms(0, 0, 0),
+        };
+        match schedule {
+            None | Some("run") => Ok(DigestSchedule::EveryRun),
+            Some("daily") => Ok(DigestSchedule::Daily { time: time }),
+            Some("weekly") => {
+                let day = match day {
+                    Some(day) => match day.parse() {
+                        Ok(day) => day,
+                        Err(_) => bail!("invalid digest_day {}", day),
+                    },
+                    None => Weekday::Mon,
+                };
+                Ok(DigestSchedule::Weekly { day: day, time: time })
+            }
+            Some(schedule) => bail!("unknown digest schedule {}, expected run, daily or weekly", schedule),
+        }
+    }
+
+    /// Most recent time a digest was due, `None` when digests are sent at each run.
+    pub fn last_due(&self, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        let (days_back, period, time) = match self {
+            &DigestSchedule::EveryRun => return None,
+            &DigestSchedule::Daily { time } => (0, 1, time),
+            &DigestSchedule::Weekly { day, time } => {
+                let days_back = (7 + now.weekday().num_days_from_monday() -
+                                 day.num_days_from_monday()) % 7;
+                (days_back, 7, time)
+            }
+        };
+        let mut due = (now.date() - Duration::days(days_back as i64))
+            .and_time(time)
+            .unwrap_or(now);
+        if due > now {
+            due = due - Duration::days(period);
+        }
+        Some(due)
+    }
+}
+
+/// Parse a rate such as `20/minute` into a number of emails and a period.
+fn parse_rate(rate: &str) -> Result<(usize, StdDuration)> {
+    let mut parts = rate.splitn(2, '/');
+    let limit = match parts.next().map(|limit| limit.trim().parse()) {
+        Some(Ok(limit)) => limit,
+        _ => bail!("invalid send_rate {}, expected <count>/<second|minute|hour>", rate),
+    };
+    let period = match parts.next().map(|period| period.trim()) {
+        Some("second") => 1,
+        Some("minute") => 60,
+        Some("hour") => 3600,
+        _ => bail!("invalid send_rate {}, expected <count>/<second|minute|hour>", rate),
+    };
+    Ok((limit, StdDuration::from_secs(period)))
+}
+
 pub enum MailBackend {
     File { path: String },
     SendMail { path: Option<String> }
 }
 
+/// Delivery settings shared by the feeds of a category.
+pub struct Category {
+    /// override the recipient of the configuration file
+    pub to: Option<String>,
+    /// override the digest mode of the configuration file
+    pub digest: Option<DigestMode>,
+    /// extra headers of the emails, e.g. to sort them in a mailbox folder
+    pub headers: Vec<(String, String)>,
+}
+
+/// How the database is stored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Storage {
+    /// `rust2email.json`, rewritten at each save
+    Json,
+    /// `rust2email.sqlite`
+    Sqlite,
+}
+
 pub struct Settings {
     pub verbose: bool,
     pub text: bool,
@@ -44,24 +192,77 @@ pub struct Settings {
     pub to: String,
     pub subject: String,
     pub body: String,
+    pub digest: DigestMode,
+    pub digest_subject: String,
+    pub digest_body: String,
+    pub digest_toc_entry: String,
+    pub digest_entry: String,
+    pub digest_schedule: DigestSchedule,
+    /// path of the script run on each new entry
+    pub hook: Option<String>,
+    /// seconds before killing the external command of a feed
+    pub command_timeout: u64,
+    /// maximum number of emails sent per period, 0 for no limit
+    pub send_rate: (usize, StdDuration),
+    /// maximum number of entries sent per feed and per run, 0 for no limit
+    pub max_per_feed: usize,
+    /// minutes between two fetches of a feed in daemon mode, unless the feed tells otherwise
+    pub interval: u64,
+    /// replace the URL of the feeds which moved permanently
+    pub update_moved_feeds: bool,
+    /// failures in a row of a feed before telling `alert_to`, 0 to never tell
+    pub alert_failures: u64,
+    pub alert_to: String,
+    pub storage: Storage,
+    /// delivery settings per category, the categories are nested with `/`
+    pub categories: HashMap<String, Category>,
     pub mail: MailBackend,
 }
 
 impl Settings {
-    pub fn new(path: Option<&str>) -> Result<Self> {
-        let config_file = match path {
-            Some(path) => path.into(),
+    pub fn path(path: Option<&str>) -> Result<PathBuf> {
+        match path {
+            Some(path) => Ok(path.into()),
             None => {
                 let xdg_dirs = xdg::BaseDirectories::with_prefix("rust2email").unwrap();
-                xdg_dirs.find_config_file("rust2email.toml").unwrap()
+                match xdg_dirs.find_config_file("rust2email.toml") {
+                    Some(config_file) => Ok(config_file),
+                    None => bail!("no rust2email.toml configuration file found"),
+                }
             }
-        };
+        }
+    }
+
+    /// Where the configuration file is created when there is none.
+    pub fn default_path() -> PathBuf {
+        let xdg_dirs = xdg::BaseDirectories::with_prefix("rust2email").unwrap();
+        xdg_dirs.place_config_file("rust2email.toml").unwrap()
+    }
+
+    /// Settings of `category`, or else of the closest enclosing category.
+    pub fn category(&self, category: &str) -> Option<&Category> {
+        let mut category = category;
+        loop {
+            if let Some(settings) = self.categories.get(category) {
+                return Some(settings);
+            }
+            match category.rfind('/') {
+                Some(position) => category = &category[..position],
+                None => return None,
+            }
+        }
+    }
 
-        let mut f = File::open(config_file).unwrap();
+    pub fn new(path: Option<&str>) -> Result<Self> {
+        let config_file = Settings::path(path)?;
+
+        let mut f = File::open(&config_file)
+            .chain_err(|| format!("could not open {}", config_file.display()))?;
         let mut data = String::new();
-        f.read_to_string(&mut data).unwrap();
+        f.read_to_string(&mut data)?;
 
-        let file_config: ConfigFileSettings = toml::from_str(data.as_str()).unwrap();
+        let file_config: ConfigFileSettings = toml::from_str(data.as_str())
+            .chain_err(|| format!("could not parse {}", config_file.display()))?;
 
         let mail = match file_config.mail_backend.as_str() {
             "file" => match file_config.mail_file {
@@ -75,6 +276,44 @@ impl Settings {
             _ => bail!("wrong or no mail backend selected")
         };
 
+        let digest = match file_config.digest {
+            Some(digest) => DigestMode::from_str(&digest)?,
+            None => DigestMode::None,
+        };
+
+        let digest_schedule = DigestSchedule::new(file_config.digest_schedule.as_ref().map(|s| s.as_str()),
+                                                  file_config.digest_time.as_ref().map(|s| s.as_str()),
+                                                  file_config.digest_day.as_ref().map(|s| s.as_str()))?;
+
+        let storage = match file_config.storage.as_ref().map(|s| s.as_str()) {
+            None | Some("json") => Storage::Json,
+            Some("sqlite") => Storage::Sqlite,
+            Some(storage) => bail!("unknown storage {}, expected json or sqlite", storage),
+        };
+
+        let mut categories = HashMap::new();
+        for (name, category) in file_config.categories.unwrap_or_default() {
+            let digest = match category.digest {
+                Some(digest) => Some(DigestMode::from_str(&digest)?),
+                None => None,
+            };
+            categories.insert(name,
+                              Category {
+                                  to: category.to,
+                                  digest: digest,
+                                  headers: category.headers.unwrap_or_default().into_iter().collect(),
+                              });
+        }
+
+        let send_rate = match file_config.send_rate {
+            Some(rate) => parse_rate(&rate)?,
+            None => (0, StdDuration::from_secs(1)),
+        };
+
+        let alert_to = match file_config.alert_to {
+            Some(ref to) => to.clone(),
+            None => file_config.to.clone(),
+        };
         Ok(Settings {
                verbose: match file_config.verbose {
                    Some(verbose) => verbose,
@@ -105,7 +344,99 @@ impl Settings {
                    Some(body) => body,
                    None => "<p>URL: <entry_url></p>\r\n<entry_body>".into(),
                },
+               digest: digest,
+               digest_subject: match file_config.digest_subject {
+                   Some(subject) => subject,
+                   None => "<feed_name>: <digest_count> new entries".into(),
+               },
+               digest_body: match file_config.digest_body {
+                   Some(body) => body,
+                   None => "<ul>\r\n<digest_toc></ul>\r\n<digest_entries>".into(),
+               },
+               digest_toc_entry: match file_config.digest_toc_entry {
+                   Some(toc) => toc,
+                   None => "<li><a href=\"#entry<entry_index>\"><feed_name>: <entry_name></a></li>\r\n".into(),
+               },
+               digest_entry: match file_config.digest_entry {
+                   Some(entry) => entry,
+                   None => "<h2 id=\"entry<entry_index>\"><entry_name></h2>\r\n<p>URL: <entry_url></p>\r\n<entry_body>\r\n".into(),
+               },
+               digest_schedule: digest_schedule,
+               hook: file_config.hook,
+               command_timeout: match file_config.command_timeout {
+                   Some(timeout) => timeout,
+                   None => 30,
+               },
+               send_rate: send_rate,
+               max_per_feed: match file_config.max_per_feed {
+                   Some(max) => max,
+                   None => 0,
+               },
+               interval: match file_config.interval {
+                   Some(interval) => interval,
+                   None => 60,
+               },
+               update_moved_feeds: match file_config.update_moved_feeds {
+                   Some(update) => update,
+                   None => true,
+               },
+               alert_failures: match file_config.alert_failures {
+                   Some(failures) => failures,
+                   None => 0,
+               },
+               alert_to: alert_to,
+               storage: storage,
+               categories: categories,
                mail: mail
            })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32) -> DateTime<Local> {
+        // 2017-10-02 is a Monday
+        Local.ymd(2017, 10, day).and_hms(hour, 0, 0)
+    }
+
+    #[test]
+    fn last_due_every_run() {
+        assert_eq!(DigestSchedule::EveryRun.last_due(at(2, 12)), None);
+    }
+
+    #[test]
+    fn last_due_daily() {
+        let schedule = DigestSchedule::Daily { time: NaiveTime::from_hms(8, 0, 0) };
+        assert_eq!(schedule.last_due(at(3, 9)), Some(at(3, 8)));
+        assert_eq!(schedule.last_due(at(3, 8)), Some(at(3, 8)));
+        assert_eq!(schedule.last_due(at(3, 7)), Some(at(2, 8)));
+    }
+
+    #[test]
+    fn last_due_weekly() {
+        let schedule = DigestSchedule::Weekly {
+            day: Weekday::Mon,
+            time: NaiveTime::from_hms(8, 0, 0),
+        };
+        assert_eq!(schedule.last_due(at(2, 9)), Some(at(2, 8)));
+        assert_eq!(schedule.last_due(at(8, 23)), Some(at(2, 8)));
+        // before the time on the day itself, the previous week is due
+        assert_eq!(schedule.last_due(at(9, 7)), Some(at(2, 8)));
+        assert_eq!(schedule.last_due(at(9, 8)), Some(at(9, 8)));
+    }
+
+    #[test]
+    fn last_due_weekly_wraps_around() {
+        let schedule = DigestSchedule::Weekly {
+            day: Weekday::Sat,
+            time: NaiveTime::from_hms(18, 0, 0),
+        };
+        // from Monday back to Saturday 2017-09-30
+        assert_eq!(schedule.last_due(at(2, 12)), Some(Local.ymd(2017, 9, 30).and_hms(18, 0, 0)));
+        assert_eq!(schedule.last_due(at(7, 17)), Some(Local.ymd(2017, 9, 30).and_hms(18, 0, 0)));
+        assert_eq!(schedule.last_due(at(7, 19)), Some(at(7, 18)));
+    }
+}
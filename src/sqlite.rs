@@ -0,0 +1,173 @@
+use chrono::Local;
+use rusqlite::Connection;
+use serde_json::{self, Value};
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::path::Path;
+use feeds::Feed;
+use outbox::OutgoingEmail;
+use errors::*;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS feeds (
+    position INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS seen (
+    feed TEXT NOT NULL,
+    id TEXT NOT NULL,
+    time INTEGER NOT NULL,
+    PRIMARY KEY (feed, id)
+);
+CREATE TABLE IF NOT EXISTS outbox (
+    position INTEGER PRIMARY KEY,
+    data TEXT NOT NULL
+);
+";
+
+/// Database stored with SQLite.
+///
+/// The feeds are stored as JSON, except for their seen ids which have
+/// their own table so only the new ones are written at each save.
+pub struct Database {
+    connection: Connection,
+    /// seen ids of each feed as they are in the database
+    seen: HashMap<String, HashSet<String>>,
+}
+
+impl Database {
+    /// Open the database at `path`, a new one is created for `version`.
+    pub fn open(path: &Path, version: u32) -> Result<Self> {
+        let connection = Connection::open(path)
+            .chain_err(|| format!("could not open {}", path.display()))?;
+        connection.execute_batch(SCHEMA)?;
+        // without a version, only a database holding data comes from version 1
+        let rows: i64 = connection
+            .query_row("SELECT (SELECT COUNT(*) FROM meta) + (SELECT COUNT(*) FROM feeds)",
+                       &[],
+                       |row| row.get(0))?;
+        if rows == 0 {
+            connection.execute("INSERT INTO meta (key, value) VALUES ('version', ?)",
+                               &[&json!(version).to_string()])?;
+        }
+        Ok(Database {
+               connection: connection,
+               seen: HashMap::new(),
+           })
+    }
+
+    /// Read the whole database, in the same form as the JSON file.
+    pub fn load(&mut self) -> Result<Value> {
+        let mut data = json!({
+            "version": 1,
+            "feeds": [],
+            "last_digest": null,
+            "outbox": [],
+        });
+
+        {
+            let mut statement = self.connection.prepare("SELECT key, value FROM meta")?;
+            let rows = statement.query_map(&[], |row| (row.get::<_, String>(0), row.get::<_, String>(1)))?;
+            for row in rows {
+                let (key, value) = row?;
+                data[&key] = serde_json::from_str(&value)
+                    .chain_err(|| format!("invalid value for {}", key))?;
+            }
+        }
+
+        self.seen.clear();
+        {
+            let mut statement = self.connection.prepare("SELECT feed, id FROM seen")?;
+            let rows = statement.query_map(&[], |row| (row.get::<_, String>(0), row.get::<_, String>(1)))?;
+            for row in rows {
+                let (feed, id) = row?;
+                self.seen.entry(feed).or_insert_with(HashSet::new).insert(id);
+            }
+        }
+
+        {
+            let mut statement = self.connection.prepare("SELECT name, data FROM feeds ORDER BY position")?;
+            let rows = statement.query_map(&[], |row| (row.get::<_, String>(0), row.get::<_, String>(1)))?;
+            for row in rows {
+                let (name, feed) = row?;
+                let mut feed: Value = serde_json::from_str(&feed)
+                    .chain_err(|| format!("invalid feed {}", name))?;
+                feed["seen"] = match self.seen.get(&name) {
+                    Some(seen) => json!(seen),
+                    None => json!([]),
+                };
+                data["feeds"].as_array_mut().unwrap().push(feed);
+            }
+        }
+
+        {
+            let mut statement = self.connection.prepare("SELECT data FROM outbox ORDER BY position")?;
+            let rows = statement.query_map(&[], |row| row.get::<_, String>(0))?;
+            for row in rows {
+                let email: Value = serde_json::from_str(&row?)
+                    .chain_err(|| "invalid email in the outbox")?;
+                data["outbox"].as_array_mut().unwrap().push(email);
+            }
+        }
+
+        Ok(data)
+    }
+
+    pub fn save(&mut self,
+                version: u32,
+                feeds: &mut [Feed],
+                last_digest: Option<i64>,
+                outbox: &[OutgoingEmail])
+                -> Result<()> {
+        let now = Local::now().timestamp();
+        let transaction = self.connection.transaction()?;
+
+        transaction.execute("INSERT OR REPLACE INTO meta (key, value) VALUES ('version', ?)",
+                     &[&json!(version).to_string()])?;
+        transaction.execute("INSERT OR REPLACE INTO meta (key, value) VALUES ('last_digest', ?)",
+                     &[&json!(last_digest).to_string()])?;
+
+        transaction.execute("DELETE FROM feeds", &[])?;
+        let none = HashSet::new();
+        for (position, feed) in feeds.iter_mut().enumerate() {
+            // the seen ids are saved below
+            let seen = mem::replace(&mut feed.seen, HashSet::new());
+            let data = serde_json::to_string(feed);
+            feed.seen = seen;
+            let data = data.chain_err(|| format!("could not serialize {}", feed.name))?;
+            transaction.execute("INSERT INTO feeds (position, name, data) VALUES (?, ?, ?)",
+                         &[&(position as i64), &feed.name, &data])?;
+
+            let stored = self.seen.get(&feed.name).unwrap_or(&none);
+            for id in feed.seen.difference(stored) {
+                transaction.execute("INSERT OR IGNORE INTO seen (feed, id, time) VALUES (?, ?, ?)",
+                             &[&feed.name, id, &now])?;
+            }
+            for id in stored.difference(&feed.seen) {
+                transaction.execute("DELETE FROM seen WHERE feed = ? AND id = ?", &[&feed.name, id])?;
+            }
+        }
+        for name in self.seen.keys() {
+            if !feeds.iter().any(|feed| &feed.name == name) {
+                transaction.execute("DELETE FROM seen WHERE feed = ?", &[name])?;
+            }
+        }
+
+        transaction.execute("DELETE FROM outbox", &[])?;
+        for (position, email) in outbox.iter().enumerate() {
+            let data = serde_json::to_string(email).chain_err(|| "could not serialize the outbox")?;
+            transaction.execute("INSERT INTO outbox (position, data) VALUES (?, ?)",
+                         &[&(position as i64), &data])?;
+        }
+
+        transaction.commit()?;
+
+        self.seen = feeds.iter().map(|feed| (feed.name.clone(), feed.seen.clone())).collect();
+        Ok(())
+    }
+}
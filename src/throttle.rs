@@ -0,0 +1,36 @@
+use std::collections::VecDeque;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Limit the number of emails sent during a period of time.
+pub struct Throttle {
+    limit: usize,
+    period: Duration,
+    sent: VecDeque<Instant>,
+}
+
+impl Throttle {
+    /// `limit` emails per `period`, a limit of 0 disables throttling.
+    pub fn new(limit: usize, period: Duration) -> Self {
+        Throttle {
+            limit: limit,
+            period: period,
+            sent: VecDeque::new(),
+        }
+    }
+
+    /// Wait until another email can be sent.
+    pub fn wait(&mut self) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.sent.len() >= self.limit {
+            let oldest = self.sent.pop_front().unwrap();
+            let elapsed = oldest.elapsed();
+            if elapsed < self.period {
+                thread::sleep(self.period - elapsed);
+            }
+        }
+        self.sent.push_back(Instant::now());
+    }
+}